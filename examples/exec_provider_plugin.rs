@@ -0,0 +1,115 @@
+//! Reference provider plugin for the `exec` provider (see ADD_PROVIDER.md).
+//!
+//! It doesn't rent anything: "servers" are entries in a JSON file (`state` in the settings of
+//! the config, or `$TMPDIR/cargo-remote-example-plugin.json`) that point to `host` (default
+//! `127.0.0.1`). Useful as a starting point and to test the protocol.
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+const VERSION: u64 = 1;
+
+fn state_path(req: &Value) -> PathBuf {
+    req.pointer("/config/settings/state")
+        .and_then(|s| s.as_str())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("cargo-remote-example-plugin.json"))
+}
+
+fn load(path: &PathBuf) -> Vec<Value> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save(path: &PathBuf, servers: &[Value]) {
+    std::fs::write(path, serde_json::to_string_pretty(servers).unwrap()).unwrap();
+}
+
+fn ok(result: Value) -> Value {
+    json!({ "version": VERSION, "ok": true, "result": result })
+}
+
+fn error(msg: &str) -> Value {
+    json!({ "version": VERSION, "ok": false, "error": msg })
+}
+
+fn handle(req: &Value) -> Value {
+    if req["version"].as_u64() != Some(VERSION) {
+        return error("unsupported protocol version");
+    }
+    let path = state_path(req);
+    let mut servers = load(&path);
+    let id = req
+        .pointer("/server/id")
+        .or_else(|| req.get("id_or_name"))
+        .and_then(|i| i.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let pos = servers.iter().position(|s| s["id"] == id.as_str());
+
+    match req["op"].as_str().unwrap_or_default() {
+        "rent" => {
+            let next = servers
+                .iter()
+                .filter_map(|s| s["id"].as_str()?.parse::<u64>().ok())
+                .max()
+                .unwrap_or(0)
+                + 1;
+            let server = json!({
+                "id": next.to_string(),
+                "host": req.pointer("/config/settings/host").cloned().unwrap_or(json!("127.0.0.1")),
+                "port": 22,
+                "username": "root",
+                "status": "running",
+                "project": req["project_key"],
+                // Nothing boots, so nothing runs it and `ready_marker` stays unset
+                "user_data": req.pointer("/options/user_data").cloned().unwrap_or_default(),
+            });
+            servers.push(server.clone());
+            save(&path, &servers);
+            ok(server)
+        }
+        "delete" => match pos {
+            Some(i) => {
+                servers.remove(i);
+                save(&path, &servers);
+                ok(Value::Null)
+            }
+            None => error("no such server"),
+        },
+        "exists" => ok(json!(pos.is_some())),
+        "list" => ok(json!(servers)),
+        "find" => match pos {
+            Some(i) => ok(servers[i].clone()),
+            None => error("no such server"),
+        },
+        "status" => match pos {
+            Some(i) => ok(servers[i]["status"].clone()),
+            None => error("no such server"),
+        },
+        op @ ("power_off" | "power_on") => match pos {
+            Some(i) => {
+                servers[i]["status"] = json!(if op == "power_off" { "off" } else { "running" });
+                save(&path, &servers);
+                ok(Value::Null)
+            }
+            None => error("no such server"),
+        },
+        // Optional operations that aren't implemented are reported as unsupported
+        _ => json!({ "version": VERSION, "ok": false, "unsupported": true }),
+    }
+}
+
+fn main() {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input).unwrap();
+    let resp = match serde_json::from_str::<Value>(&input) {
+        Ok(req) => handle(&req),
+        Err(e) => error(&format!("invalid request: {}", e)),
+    };
+    println!("{}", resp);
+}
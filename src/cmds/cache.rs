@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Result};
+use clap::Subcommand;
+use inquire::Confirm;
+use std::io::IsTerminal;
+
+use crate::cmds::image::hetzner_config;
+use crate::config::SavedConfigs;
+use crate::provider::hetzner::HetznerProvider;
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCmd {
+    #[command(name = "show")]
+    /// Shows the build cache volume of a Hetzner config
+    Show {
+        #[arg(long = "config")]
+        config: String,
+    },
+    #[command(name = "destroy")]
+    /// Deletes the build cache volume with everything on it
+    Destroy {
+        #[arg(long = "config")]
+        config: String,
+        #[arg(long = "yes")]
+        /// Don't ask for confirmation
+        yes: bool,
+    },
+}
+
+pub fn cache(cmd: CacheCmd) -> Result<()> {
+    match cmd {
+        CacheCmd::Show { config } => {
+            let cfg = hetzner_config(&SavedConfigs::load()?, &config)?;
+            let enabled = cfg.volume_size;
+            let Some(v) = HetznerProvider::new(cfg)?.volume()? else {
+                match enabled {
+                    Some(size) => println!(
+                        "No build cache yet, a {} GB volume is created on the next `begin`",
+                        size
+                    ),
+                    None => println!("`{}` has no build cache (set `volume_size`)", config),
+                }
+                return Ok(());
+            };
+            println!("Volume:   {} (id={})", v.name, v.id);
+            println!("Size:     {} GB", v.size);
+            println!("Location: {}", v.location.name);
+            println!("Created:  {}", v.created);
+            match v.server {
+                Some(s) => println!("Attached: server {}", s),
+                None => println!("Attached: no"),
+            }
+            if enabled.is_none() {
+                println!("`volume_size` isn't set anymore, new sessions don't use it");
+            }
+        }
+        CacheCmd::Destroy { config, yes } => {
+            let cfg = hetzner_config(&SavedConfigs::load()?, &config)?;
+            let provider = HetznerProvider::new(cfg)?;
+            let v = provider
+                .volume()?
+                .ok_or_else(|| anyhow!("`{}` has no build cache volume", config))?;
+            if !yes {
+                if !std::io::stdin().is_terminal() {
+                    return Err(anyhow!("pass --yes to destroy the volume {}", v.name));
+                }
+                let msg = format!(
+                    "Delete volume {} ({} GB) with all cached builds?",
+                    v.name, v.size
+                );
+                if !Confirm::new(&msg).with_default(false).prompt()? {
+                    return Ok(());
+                }
+            }
+            provider.destroy_volume(&v)?;
+            println!("Deleted volume {}", v.name);
+        }
+    }
+    Ok(())
+}
@@ -3,21 +3,34 @@ use anyhow::{anyhow, Result};
 use crate::config::SavedConfigs;
 
 pub fn config_delete(name: Option<String>, index: Option<usize>) -> Result<()> {
-    let mut cfgs = SavedConfigs::load().unwrap_or_default();
-    let pos = if let Some(n) = name {
-        cfgs.items
-            .iter()
-            .position(|c| c.name() == n)
-            .ok_or_else(|| anyhow!("not found"))?
+    // The index is the one `config list` shows, of all layers
+    let name = if let Some(n) = name {
+        n
     } else if let Some(i) = index {
-        if i < cfgs.items.len() {
-            i
-        } else {
-            return Err(anyhow!("index"));
-        }
+        let all = SavedConfigs::load()?;
+        all.items
+            .get(i)
+            .map(|c| c.name().to_string())
+            .ok_or_else(|| anyhow!("index"))?
     } else {
         return Err(anyhow!("provide name or index"));
     };
+
+    // Only the user config is changed
+    let mut cfgs = SavedConfigs::load_user()?;
+    let pos = cfgs
+        .items
+        .iter()
+        .position(|c| c.name() == name)
+        .ok_or_else(|| {
+            anyhow!(
+                "`{}` is not defined in {}, see `cargo remote config show --effective`",
+                name,
+                SavedConfigs::path()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default()
+            )
+        })?;
     let removed = cfgs.items.remove(pos);
     if cfgs.default.as_deref() == Some(removed.name()) {
         cfgs.default = None;
@@ -3,7 +3,7 @@ use anyhow::Result;
 use crate::config::{mode::Mode, SavedConfigs};
 
 pub fn config_list() -> Result<()> {
-    let cfgs = SavedConfigs::load().unwrap_or_default();
+    let cfgs = SavedConfigs::load()?;
     if cfgs.items.is_empty() {
         println!("Cargo remote isn't configured. Use `cargo remote configure`");
         return Ok(());
@@ -18,6 +18,9 @@ pub fn config_list() -> Result<()> {
         let mode = match c.mode {
             Mode::Manual => "manual",
             Mode::Hetzner => "hetzner",
+            Mode::Exec => "exec",
+            Mode::DigitalOcean => "digitalocean",
+            Mode::Ec2 => "ec2",
         };
         println!("{} [{}] {} {}", i, d, c.name(), mode);
     }
@@ -15,6 +15,9 @@ pub enum ConfigCmd {
         name: Option<String>,
         #[arg(long = "index")]
         index: Option<usize>,
+        /// Shows the merged configuration and which file or variable each value comes from
+        #[arg(long = "effective", conflicts_with = "index")]
+        effective: bool,
     },
     #[command(name = "delete")]
     Delete {
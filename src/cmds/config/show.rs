@@ -1,10 +1,16 @@
 use anyhow::{anyhow, Result};
 
+use crate::config::layers::Layered;
 use crate::config::SavedConfigs;
 
-pub fn config_show(name: Option<String>, index: Option<usize>) -> Result<()> {
+pub fn config_show(name: Option<String>, index: Option<usize>, effective: bool) -> Result<()> {
+    if effective {
+        print!("{}", Layered::load()?.explain(name.as_deref())?);
+        return Ok(());
+    }
+
     println!("Path: {:?}", SavedConfigs::path().unwrap());
-    let cfgs = SavedConfigs::load().unwrap_or_default();
+    let cfgs = SavedConfigs::load()?;
 
     if cfgs.items.is_empty() {
         println!("No config found!");
@@ -12,7 +18,7 @@ pub fn config_show(name: Option<String>, index: Option<usize>) -> Result<()> {
     }
 
     if cfgs.items.len() == 1 {
-        println!("{}", toml::to_string_pretty(&cfgs.items[0])?);
+        println!("{}", toml::to_string_pretty(&cfgs.items[0].redacted())?);
         return Ok(());
     }
 
@@ -23,6 +29,6 @@ pub fn config_show(name: Option<String>, index: Option<usize>) -> Result<()> {
     } else {
         return Err(anyhow!("provide name or index"));
     };
-    println!("{}", toml::to_string_pretty(&c)?);
+    println!("{}", toml::to_string_pretty(&c.redacted())?);
     Ok(())
 }
@@ -6,7 +6,7 @@ use crate::config::{mode::MODE_VARIANTS, SavedConfigs, PRIORITY_VARIANTS};
 pub mod manual;
 
 pub fn configure_wizard() -> Result<()> {
-    let mut cfgs = SavedConfigs::load().unwrap_or_default();
+    let mut cfgs = SavedConfigs::load_user()?;
 
     println!("General config:");
     if cfgs.priority.is_none() {
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use inquire::Select;
+use std::collections::HashSet;
+use std::io::IsTerminal;
+
+use crate::config::mode::Mode;
+use crate::config::SavedConfigs;
+use crate::provider::get_provider;
+use crate::state::State;
+
+const ADOPT: &str = "Adopt (track it in servers.toml)";
+const DELETE: &str = "Delete";
+const SKIP: &str = "Skip";
+
+/// Finds servers created by cargo-remote that aren't tracked in `servers.toml`
+pub fn gc() -> Result<()> {
+    let mut st = State::load().unwrap_or_default();
+    let cfgs = SavedConfigs::load()?;
+    let interactive = std::io::stdin().is_terminal();
+
+    let known: HashSet<String> = st.projects.iter().map(|h| h.id.clone()).collect();
+    // Several configs may use the same account
+    let mut seen = HashSet::new();
+    let mut orphans = 0;
+
+    for c in cfgs.items.iter() {
+        if !Mode::check_cloud_mode(&c.mode) {
+            continue;
+        }
+        let provider = match get_provider(c) {
+            Ok(p) => p,
+            Err(e) => {
+                println!(
+                    "[{}-{}] could not use the config: {:#}",
+                    c.mode,
+                    c.name(),
+                    e
+                );
+                continue;
+            }
+        };
+        let servers = match provider.list() {
+            Ok(s) => s,
+            Err(e) => {
+                println!("[{}-{}] could not list servers: {:#}", c.mode, c.name(), e);
+                continue;
+            }
+        };
+
+        for h in servers {
+            if known.contains(&h.id) || !seen.insert(h.id.clone()) {
+                continue;
+            }
+            orphans += 1;
+            // Named after the config that rented it, which may not be the one listing it
+            println!(
+                "[{}-{}] orphaned server {} (id={})",
+                c.mode, h.config, h.host, h.id
+            );
+            if !interactive {
+                continue;
+            }
+
+            match Select::new("What should happen?", vec![ADOPT, DELETE, SKIP]).prompt()? {
+                ADOPT => {
+                    st.projects.push(h);
+                    st.save()?;
+                }
+                DELETE => {
+                    if let Err(e) = provider.delete(&h) {
+                        println!(
+                            "[{}-{}] could not delete {} (id={}): {:#}",
+                            c.mode, h.config, h.host, h.id, e
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if orphans == 0 {
+        println!("No orphaned servers");
+    }
+    Ok(())
+}
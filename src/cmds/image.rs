@@ -0,0 +1,255 @@
+use anyhow::{anyhow, Result};
+use clap::Subcommand;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use crate::config::mode::{ConfigData, Mode};
+use crate::config::{SavedConfig, SavedConfigs};
+use crate::core::ephemeral::{cleanup_on_interrupt, EphemeralServer};
+use crate::core::{follow_provisioning, wait_running, wait_ssh, SshTarget, DEFAULT_READY_TIMEOUT};
+use crate::provider::hetzner::config::HetznerConfig;
+use crate::provider::hetzner::HetznerProvider;
+use crate::provider::RentOptions;
+
+/// Removes everything that shouldn't end up in the snapshot. `cloud-init clean` makes Servers
+/// booted from it run their own cloud-init (and get fresh ssh host keys)
+const PREPARE_SNAPSHOT: &str =
+    "rm -rf /var/lib/cargo-remote /root/rustup-init.sh /root/remote-builds \
+    && apt-get clean && cloud-init clean --logs";
+
+#[derive(Subcommand, Debug)]
+pub enum ImageCmd {
+    #[command(name = "bake")]
+    /// Provisions a Server once and saves it as snapshot, that `begin` boots from
+    Bake {
+        #[arg(long = "config")]
+        config: String,
+        #[arg(long = "preinstall", value_delimiter = ',')]
+        preinstall: Vec<String>,
+    },
+    #[command(name = "list")]
+    /// Lists snapshots baked by cargo-remote
+    List,
+    #[command(name = "refresh")]
+    /// Bakes a new snapshot (e.g. for a new Rust release) and deletes the old one
+    Refresh {
+        #[arg(long = "config")]
+        config: String,
+        #[arg(long = "preinstall", value_delimiter = ',')]
+        preinstall: Vec<String>,
+    },
+    #[command(name = "delete")]
+    /// Deletes the snapshot of a config (it boots from its `image` again), or with `--id` any
+    /// snapshot of the config's account
+    Delete {
+        #[arg(long = "config")]
+        config: String,
+        #[arg(long = "id")]
+        id: Option<u64>,
+    },
+}
+
+pub fn image(cmd: ImageCmd) -> Result<()> {
+    match cmd {
+        ImageCmd::Bake { config, preinstall } => {
+            bake(&config, &preinstall)?;
+        }
+        ImageCmd::Refresh { config, preinstall } => {
+            if let Some(old) = bake(&config, &preinstall)? {
+                hetzner_config(&SavedConfigs::load()?, &config)
+                    .and_then(provider)?
+                    .delete_image(old)?;
+                println!("Deleted the previous snapshot {}", old);
+            }
+        }
+        ImageCmd::List => list()?,
+        ImageCmd::Delete { config, id } => delete(&config, id)?,
+    }
+    Ok(())
+}
+
+pub fn hetzner_config(cfgs: &SavedConfigs, name: &str) -> Result<HetznerConfig> {
+    match cfgs.get(name) {
+        Some(SavedConfig {
+            data: ConfigData::Hetzner(h),
+            ..
+        }) => Ok(h),
+        Some(_) => Err(anyhow!("`{}` is not a Hetzner config", name)),
+        None => Err(anyhow!("no configuration named `{}`", name)),
+    }
+}
+
+fn provider(cfg: HetznerConfig) -> Result<HetznerProvider> {
+    HetznerProvider::new(cfg)
+}
+
+/// Records the snapshot in the user config, other layers are never written
+fn save_snapshot(name: &str, snapshot: Option<u64>) -> Result<()> {
+    let mut cfgs = SavedConfigs::load_user()?;
+    let mut cfg = hetzner_config(&cfgs, name).map_err(|_| {
+        anyhow!(
+            "`{}` is not defined in {}, set `snapshot = {}` where it is defined",
+            name,
+            SavedConfigs::path()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default(),
+            snapshot.map(|s| s.to_string()).unwrap_or_default()
+        )
+    })?;
+    cfg.snapshot = snapshot;
+    cfgs.upsert(SavedConfig {
+        mode: Mode::Hetzner,
+        data: ConfigData::Hetzner(cfg),
+    });
+    cfgs.save()
+}
+
+/// Bakes a snapshot for the config and records it. Returns the snapshot it replaces
+fn bake(name: &str, preinstall: &[String]) -> Result<Option<u64>> {
+    let cfg = hetzner_config(&SavedConfigs::load()?, name)?;
+    if !PathBuf::from(&cfg.local_privat_key).is_file() {
+        return Err(anyhow!("private key missing at {}", cfg.local_privat_key));
+    }
+
+    // Always bake from the base image, with the full provisioning and without the build cache
+    let base = provider(HetznerConfig {
+        snapshot: None,
+        volume_size: None,
+        ..cfg.clone()
+    })?;
+    let opts = RentOptions {
+        preinstall: preinstall.to_vec(),
+        idle: None,
+    };
+
+    cleanup_on_interrupt()?;
+    let server = EphemeralServer::rent(Box::new(base.clone()), &format!("image-{}", name), &opts)?;
+    let handle = server.handle.clone();
+    println!("Baking on server {} (id={})", handle.host, handle.id);
+
+    let timeout = Duration::from_secs(DEFAULT_READY_TIMEOUT);
+    wait_running(&base, &handle, timeout)?;
+    let ssh = SshTarget::new(
+        PathBuf::from(&cfg.local_privat_key),
+        handle.port,
+        format!("{}@{}", handle.username, handle.host),
+    )
+    .accept_new();
+    wait_ssh(&ssh, timeout)?;
+    follow_provisioning(&ssh)?;
+
+    let status = ssh
+        .command(PREPARE_SNAPSHOT)
+        .stdin(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("could not prepare the server for the snapshot"));
+    }
+    base.shutdown(&handle)?;
+
+    println!("Creating the snapshot, this takes a few minutes");
+    let mut description = format!("cargo-remote {}", name);
+    if !preinstall.is_empty() {
+        description.push_str(&format!(" ({})", preinstall.join(",")));
+    }
+    let id = base.create_snapshot(&handle, &description)?;
+    drop(server);
+
+    // The config may have been changed in the meantime
+    let old = hetzner_config(&SavedConfigs::load()?, name)?.snapshot;
+    save_snapshot(name, Some(id))?;
+    println!("Baked snapshot {}, `{}` now boots from it", id, name);
+    Ok(old)
+}
+
+fn list() -> Result<()> {
+    let cfgs = SavedConfigs::load()?;
+    let hetzner: Vec<HetznerConfig> = cfgs
+        .items
+        .iter()
+        .filter_map(|c| match &c.data {
+            ConfigData::Hetzner(h) => Some(h.clone()),
+            _ => None,
+        })
+        .collect();
+    if hetzner.is_empty() {
+        println!("No Hetzner config found!");
+        return Ok(());
+    }
+
+    // Several configs may use the same account
+    let mut seen = HashSet::new();
+    for h in hetzner.iter() {
+        let snapshots = match provider(h.clone()).and_then(|p| p.snapshots()) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("[{}] could not list snapshots: {:#}", h.name, e);
+                continue;
+            }
+        };
+        for s in snapshots {
+            if !seen.insert(s.id) {
+                continue;
+            }
+            let used: Vec<&str> = hetzner
+                .iter()
+                .filter(|c| c.snapshot == Some(s.id))
+                .map(|c| c.name.as_str())
+                .collect();
+            println!(
+                "{}  {}  {:.1} GB  {}  {}",
+                s.id,
+                s.created,
+                s.image_size.unwrap_or(0.0),
+                s.description,
+                if used.is_empty() {
+                    "(unused)".to_string()
+                } else {
+                    format!("(used by {})", used.join(", "))
+                }
+            );
+        }
+    }
+    if seen.is_empty() {
+        println!("No snapshots baked yet, see `cargo remote image bake`");
+    }
+    Ok(())
+}
+
+fn delete(name: &str, id: Option<u64>) -> Result<()> {
+    let cfgs = SavedConfigs::load()?;
+    let cfg = hetzner_config(&cfgs, name)?;
+    match id {
+        None => {
+            let id = cfg
+                .snapshot
+                .ok_or_else(|| anyhow!("`{}` has no baked snapshot", name))?;
+            provider(cfg)?.delete_image(id)?;
+            save_snapshot(name, None)?;
+            println!(
+                "Deleted snapshot {}, `{}` boots from its image again",
+                id, name
+            );
+        }
+        Some(id) => {
+            // Snapshots belong to the account (and project) of the config's API token
+            provider(cfg)?.delete_image(id)?;
+            // Configs booting from it would fail to rent
+            for h in cfgs
+                .items
+                .iter()
+                .filter_map(|c| match &c.data {
+                    ConfigData::Hetzner(h) if h.snapshot == Some(id) => Some(h.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+            {
+                save_snapshot(&h.name, None)?;
+            }
+            println!("Deleted snapshot {}", id);
+        }
+    }
+    Ok(())
+}
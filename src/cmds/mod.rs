@@ -1,5 +1,8 @@
+pub mod cache;
 pub mod config;
 pub mod configure;
+pub mod gc;
+pub mod image;
 pub mod remote;
 pub mod session;
 pub mod status;
@@ -2,11 +2,17 @@ use std::process::exit;
 
 use log::error;
 
-use crate::{core::remote_exec, ExecOpts};
+use crate::{
+    core::{exit_code, remote_exec},
+    ExecOpts,
+};
 
 pub fn cmd_build(exec: ExecOpts, options: Vec<String>) {
-    if let Err(e) = remote_exec(exec, "build", options) {
-        error!("{}", e);
-        exit(4)
+    match remote_exec(exec, "build", options) {
+        Ok(code) => exit(code),
+        Err(e) => {
+            error!("{:#}", e);
+            exit(exit_code(&e))
+        }
     }
 }
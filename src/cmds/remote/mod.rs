@@ -1,3 +1,4 @@
 pub mod build;
 pub mod clean;
 pub mod run;
+pub mod sync;
@@ -0,0 +1,27 @@
+use std::process::exit;
+
+use log::error;
+
+use crate::{
+    core::{exit_code, session_remote, sync_dry_run, upsync, Stage},
+    ExecOpts,
+};
+
+fn sync(exec: ExecOpts, dry_run: bool) -> anyhow::Result<()> {
+    if dry_run {
+        let total = sync_dry_run(exec)?;
+        println!("Total: {} bytes", total);
+        return Ok(());
+    }
+    let s = session_remote(exec, "", vec![])?;
+    upsync(&s).map_err(|e| e.context(Stage::Sync))?;
+    println!("Synced to {}:{}", s.transport.name(), s.build_path);
+    Ok(())
+}
+
+pub fn cmd_sync(exec: ExecOpts, dry_run: bool) {
+    if let Err(e) = sync(exec, dry_run) {
+        error!("{:#}", e);
+        exit(exit_code(&e))
+    }
+}
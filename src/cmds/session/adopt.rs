@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::mode::Mode;
+use crate::config::SavedConfigs;
+use crate::core::{wait_ready, SshTarget, DEFAULT_READY_TIMEOUT};
+use crate::provider::get_provider;
+use crate::state::State;
+use crate::AdoptOpts;
+
+/// Tracks an existing cloud Server as if it was rented by `cargo remote begin`
+pub fn adopt_session(adopt: AdoptOpts) -> Result<()> {
+    let cfgs = SavedConfigs::load()?;
+    let c = cfgs
+        .get(&adopt.config)
+        .ok_or_else(|| anyhow!("no configuration named `{}`", adopt.config))?;
+    if !Mode::check_cloud_mode(&c.mode) {
+        return Err(anyhow!(
+            "`adopt` requires a cloud provider config (e.g., Hetzner)"
+        ));
+    }
+
+    let mut st = State::load().unwrap_or_default();
+    let provider = get_provider(&c)?;
+    let mut handle = provider.find(&adopt.server)?;
+    handle.ready_marker = adopt.check_ready;
+    if st.projects.iter().any(|h| h.id == handle.id) {
+        return Err(anyhow!(
+            "server {} (id={}) is already tracked",
+            handle.host,
+            handle.id
+        ));
+    }
+
+    if adopt.check_ready {
+        let ssh = SshTarget::new(
+            PathBuf::from(c.private_key_path()),
+            handle.port,
+            format!("{}@{}", handle.username, handle.host),
+        )
+        .accept_new();
+        wait_ready(&ssh, Duration::from_secs(DEFAULT_READY_TIMEOUT))?;
+    }
+
+    println!("Adopted {} (id={})", handle.host, handle.id);
+    if !handle.ready_marker {
+        println!("Builds won't wait for it to be provisioned, see `--check-ready`");
+    }
+    st.projects.push(handle);
+    st.save()?;
+    Ok(())
+}
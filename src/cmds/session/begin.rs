@@ -1,26 +1,36 @@
 use anyhow::{anyhow, Result};
 use inquire::Select;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use crate::config::mode::Mode;
+use crate::config::project::ProjectConfig;
 use crate::config::SavedConfigs;
-use crate::core::{metadata_dir, project_key_from_dir};
-use crate::provider::get_provider;
+use crate::core::{
+    follow_provisioning, project_key_from_dir, project_metadata, wait_running, wait_ssh, SshTarget,
+};
+use crate::provider::{check_guards, get_provider, IdleWatchdog, RentOptions};
 use crate::state::State;
 use crate::BeginOpts;
 
 pub fn begin_session(begin: BeginOpts) -> Result<()> {
-    let project_dir = metadata_dir(PathBuf::from("Cargo.toml"))?;
+    let metadata = project_metadata(PathBuf::from("Cargo.toml"))?;
+    let project_dir = metadata.workspace_root.clone().into_std_path_buf();
     let key = project_key_from_dir(&project_dir);
+    let project = ProjectConfig::load(&metadata)?;
 
-    let cfgs = SavedConfigs::load().unwrap_or_default();
+    let cfgs = SavedConfigs::load()?;
     if !cfgs.has_any_cloud() {
         return Err(anyhow!(
             "no cloud provider configured; add one via `cargo remote configure`"
         ));
     }
 
-    let name = begin.config.clone().or(cfgs.default.clone());
+    let name = begin
+        .config
+        .clone()
+        .or(project.config)
+        .or(cfgs.default.clone());
 
     let mut c = None;
 
@@ -72,13 +82,41 @@ pub fn begin_session(begin: BeginOpts) -> Result<()> {
     }
 
     let provider = get_provider(&c)?;
-    let handle = provider.rent(&key, &begin.preinstall)?;
+    let mut st = State::load().unwrap_or_default();
+    check_guards(&c, provider.as_ref(), &st)?;
+
+    let opts = RentOptions {
+        preinstall: begin.preinstall.clone(),
+        idle: begin.idle_timeout.map(|timeout| IdleWatchdog {
+            timeout,
+            action: begin.idle_action,
+        }),
+    };
+    let mut handle = provider.rent(&key, &opts)?;
+    handle.idle_action = opts.idle.as_ref().map(|i| i.action);
     println!(
         "Server is starting. This may take a few minutes (installing Rust and other Dependencies)"
     );
 
-    let mut st = State::load().unwrap_or_default();
-    st.projects.push(handle);
+    st.projects.push(handle.clone());
     st.save()?;
+
+    if begin.wait {
+        let timeout = Duration::from_secs(begin.timeout);
+        let start = Instant::now();
+        wait_running(provider.as_ref(), &handle, timeout)?;
+        println!("Server {} is running, waiting for ssh", handle.host);
+
+        let ssh = SshTarget::new(
+            PathBuf::from(privk),
+            handle.port,
+            format!("{}@{}", handle.username, handle.host),
+        )
+        .accept_new();
+        let remaining = timeout.saturating_sub(start.elapsed());
+        wait_ssh(&ssh, remaining)?;
+        follow_provisioning(&ssh)?;
+        println!("Server {} is ready", handle.host);
+    }
     Ok(())
 }
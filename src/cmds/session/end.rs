@@ -1,22 +1,34 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use inquire::Select;
+use std::io::IsTerminal;
 
-use crate::{config::SavedConfigs, provider::get_provider, state::State};
+use super::pause::pick;
+use crate::{config::SavedConfigs, provider::get_provider, state::State, SessionOpts};
 
-pub fn end_session() -> Result<()> {
+/// Deletes a rented cloud Server and stops tracking it
+pub fn end_session(opts: SessionOpts) -> Result<()> {
     let mut st = State::load().unwrap_or_default();
-    let config = SavedConfigs::load().unwrap();
+    let config = SavedConfigs::load()?;
 
-    let selected = Select::new("Select Session to end: ", st.projects.clone()).prompt()?;
+    // Without a selection the deletion is always confirmed interactively
+    let selected =
+        if opts.config.is_none() && opts.server_id.is_none() && std::io::stdin().is_terminal() {
+            Select::new("Select Session to end: ", st.projects.clone()).prompt()?
+        } else {
+            pick(&st, &opts, None)?
+        };
 
-    let cloud_config = config.get(&selected.config).unwrap();
+    let cloud_config = config
+        .get(&selected.config)
+        .ok_or_else(|| anyhow!("config `{}` is missing", selected.config))?;
 
     let provider = get_provider(&cloud_config)?;
 
     provider.delete(&selected)?;
 
-    st.projects.retain(|e| e.id != selected.id);
+    st.remove(&selected.id);
 
-    st.save().unwrap();
+    st.save()?;
+    println!("Deleted {} (id={})", selected.host, selected.id);
     Ok(())
 }
@@ -1,2 +1,4 @@
+pub mod adopt;
 pub mod begin;
 pub mod end;
+pub mod pause;
@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use inquire::Select;
+use std::io::IsTerminal;
+
+use crate::config::SavedConfigs;
+use crate::provider::handle::{ServerHandle, ServerState};
+use crate::provider::{get_provider, resume};
+use crate::state::State;
+use crate::SessionOpts;
+
+/// Picks a tracked session matching `opts`, among the ones in state `from` if given
+pub fn pick(st: &State, opts: &SessionOpts, from: Option<ServerState>) -> Result<ServerHandle> {
+    let mut candidates: Vec<ServerHandle> = st
+        .projects
+        .iter()
+        .filter(|h| opts.server_id.as_ref().is_none_or(|id| &h.id == id))
+        .filter(|h| opts.config.as_ref().is_none_or(|c| &h.config == c))
+        .cloned()
+        .collect();
+    // An explicit id may also be used to fix up the state, e.g. after the idle watchdog
+    if let (None, Some(from)) = (&opts.server_id, from) {
+        candidates.retain(|h| h.state == from);
+    }
+
+    match candidates.len() {
+        0 => Err(anyhow!("no matching session")),
+        1 => Ok(candidates.remove(0)),
+        _ if std::io::stdin().is_terminal() => {
+            Ok(Select::new("Select session: ", candidates).prompt()?)
+        }
+        _ => Err(anyhow!(
+            "multiple sessions match; pass --config <name> or --server-id <id>"
+        )),
+    }
+}
+
+fn set_state(st: &mut State, id: &str, state: ServerState) -> Result<()> {
+    for h in st.projects.iter_mut().filter(|h| h.id == id) {
+        h.state = state;
+    }
+    st.save()
+}
+
+/// Powers a cloud Server off, its disk with the `target/` dirs is kept
+pub fn pause_session(opts: SessionOpts) -> Result<()> {
+    let mut st = State::load().unwrap_or_default();
+    let cfgs = SavedConfigs::load()?;
+    let h = pick(&st, &opts, Some(ServerState::Running))?;
+    let c = cfgs
+        .get(&h.config)
+        .ok_or_else(|| anyhow!("config `{}` is missing", h.config))?;
+
+    get_provider(&c)?.power_off(&h)?;
+    set_state(&mut st, &h.id, ServerState::Paused)?;
+    println!(
+        "Paused {} (id={}). It is still billed while powered off, `cargo remote end` deletes it",
+        h.host, h.id
+    );
+    Ok(())
+}
+
+pub fn resume_session(opts: SessionOpts) -> Result<()> {
+    let mut st = State::load().unwrap_or_default();
+    let cfgs = SavedConfigs::load()?;
+    let mut h = pick(&st, &opts, Some(ServerState::Paused))?;
+    let c = cfgs
+        .get(&h.config)
+        .ok_or_else(|| anyhow!("config `{}` is missing", h.config))?;
+
+    resume(get_provider(&c)?.as_ref(), &mut h)?;
+    for s in st.projects.iter_mut().filter(|s| s.id == h.id) {
+        s.host = h.host.clone();
+    }
+    set_state(&mut st, &h.id, ServerState::Running)?;
+    println!("Resumed {} (id={})", h.host, h.id);
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use anyhow::{bail, Result};
+use std::collections::HashMap;
 use std::net::{IpAddr, ToSocketAddrs};
 use std::os::unix::process::ExitStatusExt;
 use std::process::Command;
@@ -6,7 +7,10 @@ use std::time::Duration;
 
 use crate::config::mode::Mode;
 use crate::config::SavedConfigs;
-use crate::provider::provider_exists;
+use crate::core::sh_quote;
+use crate::provider::cloud_init::READY_CHECK;
+use crate::provider::handle::{ServerHandle, ServerState};
+use crate::provider::{get_provider, CloudConfig, Existence, Guards, IdleAction, Provider};
 use crate::state::State;
 
 fn resolve_ip(host: &str) -> Result<IpAddr> {
@@ -34,18 +38,41 @@ fn resolve_ip(host: &str) -> Result<IpAddr> {
 
 pub fn ping_server(target: &str) -> Result<bool> {
     let ip = resolve_ip(target)?;
-    let p = ping::new(ip.to_string().parse()?)
-        .timeout(Duration::from_secs(2))
-        .ttl(128)
-        .send();
+    let p = ping::ping(ping::Request::new(ip).ttl(128), Duration::from_secs(2));
     Ok(p.is_ok())
 }
 
+/// `up 3h 12m, ~0.05 (cx22 at 0.0080/h)`
+fn print_cost(h: &ServerHandle, guards: &Guards) {
+    let Some(uptime) = h.uptime() else {
+        return;
+    };
+    let mins = uptime.as_secs() / 60;
+    let mut line = format!("    up {}h {}m", mins / 60, mins % 60);
+    if let (Some(cost), Some(price)) = (h.cost(), h.hourly_price) {
+        line.push_str(&format!(
+            ", ~{:.2} ({} at {:.4}/h)",
+            cost,
+            h.server_type.as_deref().unwrap_or("?"),
+            price
+        ));
+    }
+    if let Some(max) = guards.max_hours {
+        if uptime.as_secs_f64() / 3600.0 > max {
+            line.push_str(&format!(", longer than max_hours = {}", max));
+        }
+    }
+    println!("{}", line);
+}
+
 pub fn status() -> Result<()> {
     let mut st = State::load().unwrap_or_default();
-    let cfgs = SavedConfigs::load().unwrap_or_default();
+    let cfgs = SavedConfigs::load()?;
     let mut changed = false;
     let mut rm = vec![];
+    let mut paused = vec![];
+    // One provider per config, resolving a secret may prompt (e.g. gpg)
+    let mut providers: HashMap<String, Result<Box<dyn Provider>, String>> = HashMap::new();
 
     println!("Available Servers: ");
     for c in cfgs.items.iter() {
@@ -70,9 +97,81 @@ pub fn status() -> Result<()> {
     }
     for h in st.projects.iter() {
         if let Some(c) = cfgs.get(&h.config) {
-            if !provider_exists(&c, h)? {
+            let provider = providers
+                .entry(c.name().to_string())
+                .or_insert_with(|| get_provider(&c).map_err(|e| format!("{:#}", e)));
+            // `None` if the Server is gone. Only `exists` may prune, other failures keep it
+            let status = match provider {
+                Err(e) => Err(e.clone()),
+                Ok(p) => match p.exists(h) {
+                    Existence::Exists => p.status(h).map(Some).map_err(|e| format!("{:#}", e)),
+                    Existence::Gone => Ok(None),
+                    Existence::Unknown(reason) => Err(reason),
+                },
+            };
+            if let Ok(None) = status {
+                println!("[{}-{}] {} deleted, removing it", c.mode, c.name(), h.host);
                 rm.push(h.clone());
                 changed = true;
+            } else if let Err(reason) = status {
+                println!(
+                    "[{}-{}] {} (id={}) unknown, keeping it: {}",
+                    c.mode,
+                    c.name(),
+                    h.host,
+                    h.id,
+                    reason
+                );
+            } else if status.as_ref().is_ok_and(|s| s.as_deref() == Some("off")) {
+                if h.state == ServerState::Paused {
+                    println!("[{}-{}] {} paused", c.mode, c.name(), h.host);
+                    print_cost(h, &c.data.guards());
+                } else if h.state == ServerState::Ephemeral {
+                    // e.g. shut down for the snapshot by `image bake`
+                    println!(
+                        "[{}-{}] {} powered off, ephemeral (`cargo remote end` deletes it)",
+                        c.mode,
+                        c.name(),
+                        h.host
+                    );
+                    print_cost(h, &c.data.guards());
+                } else if h.idle_action == Some(IdleAction::PoweroffThenReap) {
+                    // The idle watchdog only powers off, deleting needs the API token
+                    let deleted = match provider {
+                        Ok(p) => p.delete(h).map_err(|e| format!("{:#}", e)),
+                        Err(e) => Err(e.clone()),
+                    };
+                    match deleted {
+                        Ok(()) => {
+                            println!(
+                                "[{}-{}] {} powered off by the idle watchdog, deleted it",
+                                c.mode,
+                                c.name(),
+                                h.host
+                            );
+                            rm.push(h.clone());
+                            changed = true;
+                        }
+                        Err(e) => println!(
+                            "[{}-{}] {} powered off by the idle watchdog, could not delete it: {}",
+                            c.mode,
+                            c.name(),
+                            h.host,
+                            e
+                        ),
+                    }
+                } else {
+                    // Shut down by the idle watchdog (or by hand). Builds offer to resume it
+                    println!(
+                        "[{}-{}] {} powered off, marked as paused (`cargo remote resume` starts it)",
+                        c.mode,
+                        c.name(),
+                        h.host
+                    );
+                    print_cost(h, &c.data.guards());
+                    paused.push(h.id.clone());
+                    changed = true;
+                }
             } else {
                 let privk = c.private_key_path();
                 let ssh_base = |args: &[&str]| {
@@ -87,12 +186,19 @@ pub fn status() -> Result<()> {
                     }
                     cmd
                 };
-                let ready = ssh_base(&["test", "-f", "/root/ready"])
+                // Adopted Servers without our cloud-init recipe never get the marker
+                if !h.ready_marker {
+                    println!("[{}-{}] {} ready: n/a", c.mode, c.name(), h.host);
+                    print_cost(h, &c.data.guards());
+                    continue;
+                }
+                let ready = ssh_base(&[format!("sh -c {}", sh_quote(READY_CHECK)).as_str()])
                     .status()
                     .map(|s| s.success())
                     .unwrap_or(false);
 
                 println!("[{}-{}] {} ready: {}", c.mode, c.name(), h.host, ready);
+                print_cost(h, &c.data.guards());
 
                 if !ready {
                     let out = ssh_base(&["cloud-init", "status"])
@@ -108,17 +214,34 @@ pub fn status() -> Result<()> {
                 }
             }
         } else {
-            rm.push(h.clone());
-            changed = true;
+            // Without the config we can't ask the provider, the Server might still be billed
+            println!(
+                "[{}] {} (id={}) unknown, config `{}` is missing",
+                h.provider.to_string(),
+                h.host,
+                h.id,
+                h.config
+            );
         }
     }
-    let mut tmp = vec![];
-    for i in st.projects {
-        if !rm.contains(&i) {
-            tmp.push(i);
+    for h in rm.iter() {
+        st.remove(&h.id);
+    }
+    for h in st.projects.iter_mut().filter(|h| paused.contains(&h.id)) {
+        h.state = ServerState::Paused;
+    }
+
+    for c in cfgs.items.iter() {
+        if let Some(budget) = c.data.guards().monthly_budget {
+            println!(
+                "[{}-{}] spent this month: {:.2} of {:.2}",
+                c.mode,
+                c.name(),
+                st.monthly_cost(c.name()),
+                budget
+            );
         }
     }
-    st.projects = tmp;
 
     if changed {
         st.save()?;
@@ -0,0 +1,397 @@
+//! Where the configs come from. Later layers win:
+//!
+//! 1. defaults for a host defined by environment variables (only if `CARGO_REMOTE_HOST` is set)
+//! 2. the system wide / team config, `CARGO_REMOTE_SYSTEM_CONFIG` or `/etc/cargo-remote/config.toml`
+//! 3. the user config `~/.config/cargo-remote/config.toml`, the only one `configure` writes
+//! 4. drop-ins `~/.config/cargo-remote/config.d/*.toml`, in file name order
+//! 5. `CARGO_REMOTE_*` environment variables, host keys only change an item of the layers above
+//!
+//! Items are merged by `name`, so a later layer only needs the name and the keys it changes.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use config::{ConfigError, Map, Source, Value, ValueKind};
+use log::warn;
+
+use crate::config::{SavedConfig, SavedConfigs};
+
+pub const SYSTEM_CONFIG: &str = "/etc/cargo-remote/config.toml";
+
+/// Name of the item defined by `CARGO_REMOTE_HOST` unless `CARGO_REMOTE_NAME` is set
+pub const ENV_ITEM: &str = "env";
+
+/// Item keys that can be set by environment variables
+const ENV_KEYS: [(&str, &str); 5] = [
+    ("CARGO_REMOTE_HOST", "host"),
+    ("CARGO_REMOTE_USER", "user"),
+    ("CARGO_REMOTE_PORT", "port"),
+    ("CARGO_REMOTE_KEY", "ssh_private_key_path"),
+    ("CARGO_REMOTE_PUBLIC_KEY", "ssh_public_key_path"),
+];
+
+/// Keys whose literal values are not printed
+const SECRET_KEYS: [&str; 2] = ["api_key", "api_token"];
+
+/// One source of configuration values, each value knows where it came from
+#[derive(Debug, Clone)]
+pub struct Layer {
+    /// Path of the file, `environment` or `defaults`
+    pub source: String,
+    values: Map<String, Value>,
+    /// Item names in the order they are defined
+    names: Vec<String>,
+}
+
+impl Source for Layer {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> std::result::Result<Map<String, Value>, ConfigError> {
+        Ok(self.values.clone())
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn value(v: toml::Value, origin: &String) -> Value {
+    let kind = match v {
+        toml::Value::String(s) => ValueKind::String(s),
+        toml::Value::Integer(i) => ValueKind::I64(i),
+        toml::Value::Float(f) => ValueKind::Float(f),
+        toml::Value::Boolean(b) => ValueKind::Boolean(b),
+        toml::Value::Datetime(d) => ValueKind::String(d.to_string()),
+        toml::Value::Array(a) => {
+            ValueKind::Array(a.into_iter().map(|v| value(v, origin)).collect())
+        }
+        toml::Value::Table(t) => {
+            ValueKind::Table(t.into_iter().map(|(k, v)| (k, value(v, origin))).collect())
+        }
+    };
+    Value::new(Some(origin), kind)
+}
+
+impl Layer {
+    /// A config file, `None` if it doesn't exist. `items` is turned into a table by name
+    fn file(path: &Path) -> Result<Option<Layer>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let source = path.display().to_string();
+        let mut table: toml::Table = toml::from_str(&std::fs::read_to_string(path)?)
+            .with_context(|| format!("invalid config file {}", source))?;
+
+        let mut names = Vec::new();
+        if let Some(items) = table.remove("items") {
+            let toml::Value::Array(items) = items else {
+                return Err(anyhow!("{}: `items` must be an array", source));
+            };
+            let mut by_name = toml::Table::new();
+            for item in items {
+                let name = item
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .ok_or_else(|| anyhow!("{}: every item needs a `name`", source))?
+                    .to_string();
+                if by_name.insert(name.clone(), item).is_some() {
+                    return Err(anyhow!("{}: `{}` is defined twice", source, name));
+                }
+                names.push(name);
+            }
+            table.insert("items".into(), toml::Value::Table(by_name));
+        }
+
+        let values = table
+            .into_iter()
+            .map(|(k, v)| (k, value(v, &source)))
+            .collect();
+        Ok(Some(Layer {
+            source,
+            values,
+            names,
+        }))
+    }
+
+    fn item(source: &str, name: &str, values: Map<String, Value>) -> Layer {
+        let mut items = Map::new();
+        items.insert(name.to_string(), Value::new(None, values));
+        let mut root = Map::new();
+        root.insert("items".to_string(), Value::new(None, items));
+        Layer {
+            source: source.to_string(),
+            values: root,
+            names: vec![name.to_string()],
+        }
+    }
+
+    /// A manual host on `CARGO_REMOTE_HOST`: root on port 22 with `~/.ssh/id_ed25519`
+    fn env_defaults() -> Option<Layer> {
+        env("CARGO_REMOTE_HOST")?;
+        let name = env("CARGO_REMOTE_NAME").unwrap_or_else(|| ENV_ITEM.into());
+        let key = env("CARGO_REMOTE_KEY").unwrap_or_else(|| {
+            let home = env("HOME").unwrap_or_default();
+            format!("{}/.ssh/id_ed25519", home)
+        });
+        let origin = "default".to_string();
+        let mut values = Map::new();
+        for (k, v) in [
+            ("name", ValueKind::String(name.clone())),
+            ("mode", ValueKind::String("manual".into())),
+            ("user", ValueKind::String("root".into())),
+            ("port", ValueKind::I64(22)),
+            ("ssh_private_key_path", ValueKind::String(key.clone())),
+            (
+                "ssh_public_key_path",
+                ValueKind::String(format!("{}.pub", key)),
+            ),
+        ] {
+            values.insert(k.to_string(), Value::new(Some(&origin), v));
+        }
+        Some(Layer::item("defaults", &name, values))
+    }
+
+    /// `CARGO_REMOTE_*` variables, e.g. the build host of a CI job. Host keys are ignored unless
+    /// their item is among `defined` (the defaults of `CARGO_REMOTE_HOST` define it as well)
+    fn environment(defined: &[String]) -> Result<Option<Layer>> {
+        let name = env("CARGO_REMOTE_NAME").unwrap_or_else(|| ENV_ITEM.into());
+        let mut values = Map::new();
+        for (var, key) in ENV_KEYS {
+            let Some(v) = env(var) else {
+                continue;
+            };
+            let kind = if key == "port" {
+                let port: u16 = v
+                    .parse()
+                    .map_err(|_| anyhow!("{} is not a port number: {}", var, v))?;
+                ValueKind::I64(port.into())
+            } else {
+                ValueKind::String(v)
+            };
+            values.insert(
+                key.to_string(),
+                Value::new(Some(&format!("${}", var)), kind),
+            );
+        }
+
+        if !values.is_empty() && !defined.contains(&name) {
+            warn!(
+                "ignoring CARGO_REMOTE_* host variables: no config named `{}` and CARGO_REMOTE_HOST is not set",
+                name
+            );
+            values.clear();
+        }
+        let mut layer = if values.is_empty() {
+            Layer {
+                source: "environment".into(),
+                values: Map::new(),
+                names: Vec::new(),
+            }
+        } else {
+            let origin = if env("CARGO_REMOTE_NAME").is_some() {
+                "$CARGO_REMOTE_NAME"
+            } else {
+                "default"
+            };
+            values.insert(
+                "name".into(),
+                Value::new(Some(&origin.to_string()), name.clone()),
+            );
+            Layer::item("environment", &name, values)
+        };
+
+        // A host defined by the environment (e.g. of a CI job) is the one to use
+        let default = match env("CARGO_REMOTE_DEFAULT") {
+            Some(d) => Some((d, "$CARGO_REMOTE_DEFAULT")),
+            None => env("CARGO_REMOTE_HOST").map(|_| (name, "$CARGO_REMOTE_HOST")),
+        };
+        if let Some((d, origin)) = default {
+            layer
+                .values
+                .insert("default".into(), Value::new(Some(&origin.to_string()), d));
+        }
+        if let Some(p) = env("CARGO_REMOTE_PRIORITY") {
+            layer.values.insert(
+                "priority".into(),
+                Value::new(Some(&"$CARGO_REMOTE_PRIORITY".to_string()), p),
+            );
+        }
+
+        Ok(Some(layer).filter(|l| !l.values.is_empty()))
+    }
+}
+
+/// The system wide config, if it exists
+pub fn system_path() -> PathBuf {
+    env("CARGO_REMOTE_SYSTEM_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(SYSTEM_CONFIG))
+}
+
+/// Directory of the drop-in files, next to the user config
+pub fn dropin_dir() -> Result<PathBuf> {
+    Ok(SavedConfigs::path()?.with_file_name("config.d"))
+}
+
+/// The merged configuration and the layers it was built from
+pub struct Layered {
+    pub layers: Vec<Layer>,
+    root: Map<String, Value>,
+}
+
+impl Layered {
+    pub fn load() -> Result<Self> {
+        let mut files = vec![system_path(), SavedConfigs::path()?];
+        if let Ok(dir) = std::fs::read_dir(dropin_dir()?) {
+            let mut dropins: Vec<PathBuf> = dir
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+                .collect();
+            dropins.sort();
+            files.extend(dropins);
+        }
+
+        let mut layers = Vec::new();
+        layers.extend(Layer::env_defaults());
+        for f in files {
+            layers.extend(Layer::file(&f)?);
+        }
+        let defined: Vec<String> = layers.iter().flat_map(|l| l.names.clone()).collect();
+        layers.extend(Layer::environment(&defined)?);
+
+        let mut builder = config::Config::builder();
+        for l in layers.iter() {
+            builder = builder.add_source(l.clone());
+        }
+        let root = builder.build()?.collect()?;
+        Ok(Layered { layers, root })
+    }
+
+    /// Item names, in the order they are first defined
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for n in self.layers.iter().flat_map(|l| l.names.iter()) {
+            if !names.contains(n) {
+                names.push(n.clone());
+            }
+        }
+        names
+    }
+
+    fn items(&self) -> Map<String, Value> {
+        match self.root.get("items").map(|v| &v.kind) {
+            Some(ValueKind::Table(t)) => t.clone(),
+            _ => Map::new(),
+        }
+    }
+
+    pub fn configs(&self) -> Result<SavedConfigs> {
+        let mut root: toml::Table =
+            Value::new(None, ValueKind::Table(self.root.clone())).try_deserialize()?;
+        let mut by_name = match root.remove("items") {
+            Some(toml::Value::Table(t)) => t,
+            _ => toml::Table::new(),
+        };
+        let mut items = Vec::new();
+        for name in self.names() {
+            let Some(item) = by_name.remove(&name) else {
+                continue;
+            };
+            let cfg: SavedConfig = item.try_into().with_context(|| {
+                format!(
+                    "config `{}` is incomplete or invalid, see `cargo remote config show --effective`",
+                    name
+                )
+            })?;
+            items.push(cfg);
+        }
+        Ok(SavedConfigs {
+            default: root.remove("default").map(|v| v.try_into()).transpose()?,
+            priority: root.remove("priority").map(|v| v.try_into()).transpose()?,
+            items,
+        })
+    }
+
+    /// The merged values with the layer each one came from, `name` limits it to one item
+    pub fn explain(&self, name: Option<&str>) -> Result<String> {
+        let mut out = String::from("# Layers, later ones win:\n");
+        for l in self.layers.iter() {
+            out.push_str(&format!("#   {}\n", l.source));
+        }
+
+        if name.is_none() {
+            let mut top: Vec<(String, Value)> = self
+                .root
+                .iter()
+                .filter(|(k, _)| *k != "items")
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            top.sort_by(|a, b| a.0.cmp(&b.0));
+            if !top.is_empty() {
+                out.push('\n');
+            }
+            for (k, v) in top {
+                explain_value(&mut out, &key(&k), v)?;
+            }
+        }
+
+        let items = self.items();
+        let names: Vec<String> = match name {
+            Some(n) if items.contains_key(n) => vec![n.to_string()],
+            Some(n) => return Err(anyhow!("no configuration named `{}`", n)),
+            None => self.names(),
+        };
+        for n in names {
+            let Some(ValueKind::Table(item)) = items.get(&n).map(|v| v.kind.clone()) else {
+                continue;
+            };
+            out.push_str("\n[[items]]\n");
+            let mut keys: Vec<(String, Value)> = item.into_iter().collect();
+            // `name` and `mode` first, like `configure` writes them
+            keys.sort_by_key(|(k, _)| (k != "name", k != "mode", k.clone()));
+            for (k, v) in keys {
+                explain_value(&mut out, &key(&k), v)?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn key(k: &str) -> String {
+    if !k.is_empty()
+        && k.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        k.to_string()
+    } else {
+        format!("{:?}", k)
+    }
+}
+
+/// Prints `key = value  # origin`, tables as dotted keys
+fn explain_value(out: &mut String, path: &str, v: Value) -> Result<()> {
+    let origin = v.origin().unwrap_or("unknown").to_string();
+    let leaf = path.rsplit('.').next().unwrap_or(path);
+    match v.kind {
+        ValueKind::Table(t) => {
+            let mut keys: Vec<(String, Value)> = t.into_iter().collect();
+            keys.sort_by(|a, b| a.0.cmp(&b.0));
+            for (k, v) in keys {
+                explain_value(out, &format!("{}.{}", path, key(&k)), v)?;
+            }
+        }
+        ValueKind::String(_) if SECRET_KEYS.contains(&leaf) => {
+            let line = format!("{} = \"<redacted>\"", path);
+            out.push_str(&format!("{:<48} # {}\n", line, origin));
+        }
+        kind => {
+            let v: toml::Value = Value::new(None, kind).try_deserialize()?;
+            let line = format!("{} = {}", path, v);
+            out.push_str(&format!("{:<48} # {}\n", line, origin));
+        }
+    }
+    Ok(())
+}
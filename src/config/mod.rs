@@ -1,7 +1,9 @@
 use crate::config::mode::{ConfigData, Mode};
-use crate::provider::handle::ServerHandle;
-use crate::provider::CloudConfig;
-use inquire::Select;
+use crate::provider::get_provider;
+use crate::provider::handle::{ServerHandle, ServerState};
+use crate::provider::{resume, CloudConfig, Guards};
+use inquire::{Confirm, Select};
+use std::io::IsTerminal;
 use std::path::PathBuf;
 use std::{collections::HashMap, fmt};
 
@@ -10,7 +12,10 @@ use serde::{Deserialize, Serialize};
 use crate::state::State;
 use anyhow::anyhow;
 
+pub mod layers;
 pub mod mode;
+pub mod project;
+pub mod secret;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -40,6 +45,19 @@ pub struct SavedConfig {
     pub data: ConfigData,
 }
 
+impl SavedConfig {
+    /// A copy without literal secrets, for printing
+    pub fn redacted(&self) -> SavedConfig {
+        let mut c = self.clone();
+        match &mut c.data {
+            ConfigData::Hetzner(h) => h.api_key = h.api_key.redacted(),
+            ConfigData::DigitalOcean(d) => d.api_token = d.api_token.redacted(),
+            ConfigData::Manual(_) | ConfigData::Exec(_) | ConfigData::Ec2(_) => {}
+        }
+        c
+    }
+}
+
 impl fmt::Display for SavedConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} ({})", self.mode, self.data)
@@ -64,6 +82,10 @@ impl CloudConfig for ManualConfig {
     fn private_key_path(&self) -> String {
         todo!()
     }
+
+    fn guards(&self) -> Guards {
+        Guards::default()
+    }
 }
 
 impl fmt::Display for ManualConfig {
@@ -72,6 +94,17 @@ impl fmt::Display for ManualConfig {
     }
 }
 
+/// The Host selected for a remote command
+#[derive(Debug, Clone)]
+pub struct RemoteHost {
+    pub host: String,
+    pub user: String,
+    pub port: u16,
+    pub ssh_key: PathBuf,
+    /// Set if the Host is a rented cloud Server
+    pub handle: Option<ServerHandle>,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SavedConfigs {
     pub default: Option<String>,
@@ -94,7 +127,12 @@ impl SavedConfigs {
         let x = xdg::BaseDirectories::with_prefix("cargo-remote");
         Ok(x.place_config_file("config.toml")?)
     }
+    /// All layers merged, see [`layers`]
     pub fn load() -> anyhow::Result<Self> {
+        layers::Layered::load()?.configs()
+    }
+    /// Only the user config, the one to change and `save`
+    pub fn load_user() -> anyhow::Result<Self> {
         let p = Self::path()?;
         if p.is_file() {
             Ok(toml::from_str(&std::fs::read_to_string(&p)?)?)
@@ -122,12 +160,22 @@ impl SavedConfigs {
     }
 
     pub fn has_any_cloud(&self) -> bool {
-        self.items.iter().any(|c| matches!(c.mode, Mode::Hetzner))
+        self.items.iter().any(|c| Mode::check_cloud_mode(&c.mode))
     }
 
-    /// Selects a Remote Host and returns Host, User, Port, PrivatKeyPath
-    pub fn select_remote_host(&self) -> anyhow::Result<(String, String, u16, PathBuf)> {
+    /// Selects a Remote Host
+    ///
+    /// `config` and `server_id` narrow the candidates down explicitly. Without them the saved
+    /// default is honored before falling back to the priority. If `prompt` is false (or stdin
+    /// is not a terminal) an ambiguous selection is an error instead of a prompt.
+    pub fn select_remote_host(
+        &self,
+        config: Option<&str>,
+        server_id: Option<&str>,
+        prompt: bool,
+    ) -> anyhow::Result<RemoteHost> {
         let priority = self.priority.clone().unwrap_or(Priority::Ask);
+        let prompt = prompt && std::io::stdin().is_terminal();
 
         let items_by_name: HashMap<String, SavedConfig> = self
             .items
@@ -140,14 +188,14 @@ impl SavedConfigs {
         let mut cloud: Vec<(ServerHandle, SavedConfig)> = state
             .projects
             .into_iter()
+            // Other commands own ephemeral Servers and delete them when done
+            .filter(|h| h.state != ServerState::Ephemeral)
             .filter_map(|h| {
                 items_by_name
                     .get(&h.config)
                     .cloned()
-                    .and_then(|cfg| match (&cfg.mode, &cfg.data) {
-                        (Mode::Hetzner, ConfigData::Hetzner(_)) => Some((h, cfg)),
-                        _ => None,
-                    })
+                    .filter(|cfg| Mode::check_cloud_mode(&cfg.mode))
+                    .map(|cfg| (h, cfg))
             })
             .collect();
 
@@ -158,150 +206,219 @@ impl SavedConfigs {
             .cloned()
             .collect();
 
+        // Explicit selection via --server-id / --config
+        if let Some(id) = server_id {
+            cloud.retain(|(h, cfg)| h.id == id && config.is_none_or(|n| cfg.name() == n));
+            if cloud.is_empty() {
+                return Err(anyhow!("no active cloud server with id {}", id));
+            }
+            manual.clear();
+        } else if let Some(name) = config {
+            if !items_by_name.contains_key(name) {
+                return Err(anyhow!("no configuration named `{}`", name));
+            }
+            cloud.retain(|(_, cfg)| cfg.name() == name);
+            manual.retain(|c| c.name() == name);
+            if cloud.is_empty() && manual.is_empty() {
+                return Err(anyhow!(
+                    "no active cloud server for `{}`; start one with `cargo remote begin --config {}`",
+                    name,
+                    name
+                ));
+            }
+        } else if let Some(name) = self.default.as_deref() {
+            // The default only narrows the selection if it actually matches a candidate
+            let has_cloud = cloud.iter().any(|(_, cfg)| cfg.name() == name);
+            let has_manual = manual.iter().any(|c| c.name() == name);
+            if has_cloud || has_manual {
+                cloud.retain(|(_, cfg)| cfg.name() == name);
+                manual.retain(|c| c.name() == name);
+            }
+        }
+
+        let explicit = server_id.is_some() || config.is_some();
+
+        // Paused Servers are only considered if nothing else matches
+        let (paused, mut cloud): (Vec<_>, Vec<_>) = cloud
+            .into_iter()
+            .partition(|(h, _)| h.state == ServerState::Paused);
+        let only_paused = cloud.is_empty()
+            && !paused.is_empty()
+            && (manual.is_empty() || (!explicit && matches!(priority, Priority::Cloud)));
+        if only_paused {
+            return resume_paused(paused, prompt);
+        }
+
         enum Selection {
             Manual(SavedConfig),
             Cloud((ServerHandle, SavedConfig)),
         }
 
-        let pick = match priority {
-            Priority::Cloud => {
-                if cloud.is_empty() {
-                    return Err(anyhow!(
-                        "no active cloud servers; start one with `cargo remote begin`"
-                    ));
-                }
-                if cloud.len() == 1 {
+        let pick = if explicit || matches!(priority, Priority::Ask) {
+            if cloud.is_empty() && manual.is_empty() {
+                return Err(anyhow!(
+                    "no configurations available; run `cargo remote configure`"
+                ));
+            }
+            if cloud.len() + manual.len() == 1 {
+                if !cloud.is_empty() {
                     Selection::Cloud(cloud.remove(0))
                 } else {
-                    let choices: Vec<String> = cloud
-                        .iter()
-                        .map(|(h, cfg)| {
-                            format!(
-                                "{} [{} {}:{} id={}]",
-                                cfg.name(),
-                                h.provider.to_string(),
-                                h.host,
-                                h.port,
-                                h.id
-                            )
-                        })
-                        .collect();
-                    let selected = Select::new("Select cloud server", choices).prompt()?;
-                    let sel_name = selected
-                        .split_once(" [")
-                        .map(|(n, _)| n.to_string())
-                        .unwrap_or(selected);
-                    let idx = cloud
-                        .iter()
-                        .position(|(_, cfg)| cfg.name() == sel_name.as_str())
-                        .ok_or_else(|| anyhow!("selection not found"))?;
-                    Selection::Cloud(cloud.remove(idx))
-                }
-            }
-            Priority::Manual => {
-                if manual.is_empty() {
-                    return Err(anyhow!(
-                        "no manual server configured; run `cargo remote configure`"
-                    ));
-                }
-                if manual.len() == 1 {
                     Selection::Manual(manual.remove(0))
-                } else {
-                    let choices: Vec<String> = manual
-                        .iter()
-                        .map(|c| match &c.data {
-                            ConfigData::Manual(m) => {
-                                format!("{} [manual {}:{}]", c.name(), m.host, m.port)
-                            }
-                            _ => c.name().to_string(),
-                        })
-                        .collect();
-                    let selected = Select::new("Select manual configuration", choices).prompt()?;
-                    let sel_name = selected
-                        .split_once(" [")
-                        .map(|(n, _)| n.to_string())
-                        .unwrap_or(selected);
-                    let idx = manual
-                        .iter()
-                        .position(|c| c.name() == sel_name.as_str())
-                        .ok_or_else(|| anyhow!("selection not found"))?;
-                    Selection::Manual(manual.remove(idx))
                 }
-            }
-            Priority::Ask => {
-                if cloud.is_empty() && manual.is_empty() {
-                    return Err(anyhow!(
-                        "no configurations available; run `cargo remote configure`"
-                    ));
+            } else {
+                let mut choices: Vec<String> = Vec::new();
+                for (h, cfg) in cloud.iter() {
+                    choices.push(cloud_label(h, cfg));
                 }
-                if cloud.len() + manual.len() == 1 {
-                    if !cloud.is_empty() {
-                        Selection::Cloud(cloud.remove(0))
-                    } else {
-                        Selection::Manual(manual.remove(0))
-                    }
+                for c in manual.iter() {
+                    choices.push(manual_label(c));
+                }
+
+                let selected = choose("Select configuration", choices, prompt)?;
+
+                if let Some(i) = cloud
+                    .iter()
+                    .position(|(h, cfg)| cloud_label(h, cfg) == selected)
+                {
+                    Selection::Cloud(cloud.remove(i))
+                } else if let Some(i) = manual.iter().position(|c| manual_label(c) == selected) {
+                    Selection::Manual(manual.remove(i))
                 } else {
-                    let mut choices: Vec<String> = Vec::new();
-                    for (h, cfg) in cloud.iter() {
-                        choices.push(format!(
-                            "{} [{} {}:{} id={}]",
-                            cfg.name(),
-                            h.provider.to_string(),
-                            h.host,
-                            h.port,
-                            h.id
-                        ));
-                    }
-                    for c in manual.iter() {
-                        choices.push(match &c.data {
-                            ConfigData::Manual(m) => {
-                                format!("{} [manual {}:{}]", c.name(), m.host, m.port)
-                            }
-                            _ => c.name().to_string(),
-                        });
-                    }
-
-                    let selected = Select::new("Select configuration", choices).prompt()?;
-                    let sel_name = selected
-                        .split_once(" [")
-                        .map(|(n, _)| n.to_string())
-                        .unwrap_or(selected);
-
-                    if let Some(i) = cloud
-                        .iter()
-                        .position(|(_, cfg)| cfg.name() == sel_name.as_str())
-                    {
-                        Selection::Cloud(cloud.remove(i))
-                    } else if let Some(i) =
-                        manual.iter().position(|c| c.name() == sel_name.as_str())
-                    {
-                        Selection::Manual(manual.remove(i))
-                    } else {
-                        return Err(anyhow!("selection not found"));
-                    }
+                    return Err(anyhow!("selection not found"));
                 }
             }
+        } else if matches!(priority, Priority::Cloud) {
+            if cloud.is_empty() {
+                return Err(anyhow!(
+                    "no active cloud servers; start one with `cargo remote begin`"
+                ));
+            }
+            if cloud.len() == 1 {
+                Selection::Cloud(cloud.remove(0))
+            } else {
+                let choices: Vec<String> =
+                    cloud.iter().map(|(h, cfg)| cloud_label(h, cfg)).collect();
+                let selected = choose("Select cloud server", choices, prompt)?;
+                let idx = cloud
+                    .iter()
+                    .position(|(h, cfg)| cloud_label(h, cfg) == selected)
+                    .ok_or_else(|| anyhow!("selection not found"))?;
+                Selection::Cloud(cloud.remove(idx))
+            }
+        } else {
+            if manual.is_empty() {
+                return Err(anyhow!(
+                    "no manual server configured; run `cargo remote configure`"
+                ));
+            }
+            if manual.len() == 1 {
+                Selection::Manual(manual.remove(0))
+            } else {
+                let choices: Vec<String> = manual.iter().map(manual_label).collect();
+                let selected = choose("Select manual configuration", choices, prompt)?;
+                let idx = manual
+                    .iter()
+                    .position(|c| manual_label(c) == selected)
+                    .ok_or_else(|| anyhow!("selection not found"))?;
+                Selection::Manual(manual.remove(idx))
+            }
         };
 
         match pick {
             Selection::Manual(cfg) => match &cfg.data {
-                ConfigData::Manual(m) => {
-                    let host = m.host.clone();
-                    let user = m.user.clone();
-                    let port = m.port;
-                    let privk = PathBuf::from(&m.ssh_private_key_path);
-                    Ok((host, user, port, privk))
-                }
+                ConfigData::Manual(m) => Ok(RemoteHost {
+                    host: m.host.clone(),
+                    user: m.user.clone(),
+                    port: m.port,
+                    ssh_key: PathBuf::from(&m.ssh_private_key_path),
+                    handle: None,
+                }),
                 _ => Err(anyhow!("invalid manual config")),
             },
-            Selection::Cloud((h, cfg)) => {
-                let host = h.host.clone();
-                let user = h.username.clone();
-                let port = h.port;
-                let privk = PathBuf::from(cfg.data.private_key_path());
+            Selection::Cloud((h, cfg)) => Ok(RemoteHost {
+                host: h.host.clone(),
+                user: h.username.clone(),
+                port: h.port,
+                ssh_key: PathBuf::from(cfg.data.private_key_path()),
+                handle: Some(h),
+            }),
+        }
+    }
+}
 
-                Ok((host, user, port, privk))
-            }
+/// Offers to resume one of the paused Servers
+fn resume_paused(
+    mut paused: Vec<(ServerHandle, SavedConfig)>,
+    prompt: bool,
+) -> anyhow::Result<RemoteHost> {
+    let labels: Vec<String> = paused.iter().map(|(h, c)| cloud_label(h, c)).collect();
+    if !prompt {
+        return Err(anyhow!(
+            "only paused servers match ({}); run `cargo remote resume`",
+            labels.join(", ")
+        ));
+    }
+    let idx = if paused.len() == 1 {
+        if !Confirm::new(&format!("{} is paused. Resume it?", labels[0]))
+            .with_default(true)
+            .prompt()?
+        {
+            return Err(anyhow!("server is paused"));
         }
+        0
+    } else {
+        let selected =
+            Select::new("Only paused servers match. Resume:", labels.clone()).prompt()?;
+        labels
+            .iter()
+            .position(|l| *l == selected)
+            .ok_or_else(|| anyhow!("selection not found"))?
+    };
+    let (mut h, cfg) = paused.remove(idx);
+
+    resume(get_provider(&cfg)?.as_ref(), &mut h)?;
+    let mut st = State::load().unwrap_or_default();
+    for s in st.projects.iter_mut().filter(|s| s.id == h.id) {
+        s.state = ServerState::Running;
+        s.host = h.host.clone();
+    }
+    st.save()?;
+
+    Ok(RemoteHost {
+        host: h.host.clone(),
+        user: h.username.clone(),
+        port: h.port,
+        ssh_key: PathBuf::from(cfg.data.private_key_path()),
+        handle: Some(h),
+    })
+}
+
+fn cloud_label(h: &ServerHandle, cfg: &SavedConfig) -> String {
+    format!(
+        "{} [{} {}:{} id={}]",
+        cfg.name(),
+        h.provider.to_string(),
+        h.host,
+        h.port,
+        h.id
+    )
+}
+
+fn manual_label(c: &SavedConfig) -> String {
+    match &c.data {
+        ConfigData::Manual(m) => format!("{} [manual {}:{}]", c.name(), m.host, m.port),
+        _ => c.name().to_string(),
+    }
+}
+
+/// Asks the user to pick one of `choices`, or fails if prompting isn't allowed
+fn choose(message: &str, choices: Vec<String>, prompt: bool) -> anyhow::Result<String> {
+    if !prompt {
+        return Err(anyhow!(
+            "multiple remote hosts match ({}); pass --config <name> or --server-id <id>",
+            choices.join(", ")
+        ));
     }
+    Ok(Select::new(message, choices).prompt()?)
 }
@@ -3,22 +3,40 @@ use std::fmt;
 use enum_dispatch::enum_dispatch;
 use serde::{Deserialize, Serialize};
 
-use crate::{config::ManualConfig, provider::hetzner::config::HetznerConfig};
+use crate::{
+    config::ManualConfig,
+    provider::{
+        digitalocean::config::DigitalOceanConfig, ec2::config::Ec2Config, exec::config::ExecConfig,
+        hetzner::config::HetznerConfig,
+    },
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum Mode {
     Manual,
     Hetzner,
+    Exec,
+    DigitalOcean,
+    Ec2,
 }
 
-pub const MODE_VARIANTS: [Mode; 2] = [Mode::Manual, Mode::Hetzner];
+pub const MODE_VARIANTS: [Mode; 5] = [
+    Mode::Manual,
+    Mode::Hetzner,
+    Mode::DigitalOcean,
+    Mode::Ec2,
+    Mode::Exec,
+];
 
 impl fmt::Display for Mode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let out = match self {
             Mode::Manual => "Manual",
             Mode::Hetzner => "Hetzner",
+            Mode::Exec => "Exec (provider plugin)",
+            Mode::DigitalOcean => "DigitalOcean",
+            Mode::Ec2 => "AWS EC2",
         };
 
         write!(f, "{}", out)
@@ -38,12 +56,18 @@ impl Mode {
 pub enum ConfigData {
     Manual(ManualConfig),
     Hetzner(HetznerConfig),
+    Exec(ExecConfig),
+    DigitalOcean(DigitalOceanConfig),
+    Ec2(Ec2Config),
 }
 impl fmt::Display for ConfigData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ConfigData::Manual(manual_config) => write!(f, "{}", manual_config),
             ConfigData::Hetzner(hetzner_config) => write!(f, "{}", hetzner_config),
+            ConfigData::Exec(exec_config) => write!(f, "{}", exec_config),
+            ConfigData::DigitalOcean(do_config) => write!(f, "{}", do_config),
+            ConfigData::Ec2(ec2_config) => write!(f, "{}", ec2_config),
         }
     }
 }
@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use cargo_metadata::Metadata;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Priority;
+use crate::ExecOpts;
+
+pub const PROJECT_CONFIG_FILE: &str = ".cargo-remote.toml";
+
+/// Per-project settings. Read from `.cargo-remote.toml` in the workspace root,
+/// `[workspace.metadata.remote]` and `[package.metadata.remote]` (in that order of precedence).
+/// CLI flags always win over these values.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Saved config that should be used for this project
+    pub config: Option<String>,
+    pub priority: Option<Priority>,
+    pub build_env: Option<String>,
+    pub rustup_default: Option<String>,
+    pub copy_back: Option<String>,
+    pub no_copy_lock: Option<bool>,
+    pub transfer_hidden: Option<bool>,
+    pub no_gitignore: Option<bool>,
+    pub ready_timeout: Option<u64>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl ProjectConfig {
+    pub fn path(project_dir: &Path) -> PathBuf {
+        project_dir.join(PROJECT_CONFIG_FILE)
+    }
+
+    pub fn load(metadata: &Metadata) -> Result<Self> {
+        let mut cfg = Self::default();
+
+        if let Some(pkg) = metadata.root_package() {
+            cfg = Self::from_metadata(&pkg.metadata)
+                .context("invalid [package.metadata.remote]")?
+                .or(cfg);
+        }
+        cfg = Self::from_metadata(&metadata.workspace_metadata)
+            .context("invalid [workspace.metadata.remote]")?
+            .or(cfg);
+
+        let p = Self::path(metadata.workspace_root.as_std_path());
+        if p.is_file() {
+            let file: Self = toml::from_str(&std::fs::read_to_string(&p)?)
+                .map_err(|e| anyhow!("invalid {}: {}", p.display(), e))?;
+            cfg = file.or(cfg);
+        }
+        Ok(cfg)
+    }
+
+    fn from_metadata(v: &serde_json::Value) -> Result<Self> {
+        match v.get("remote") {
+            Some(remote) => Ok(serde_json::from_value(remote.clone())?),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Layers `self` over `lower`
+    fn or(self, lower: Self) -> Self {
+        let mut exclude = lower.exclude;
+        exclude.extend(self.exclude);
+        Self {
+            config: self.config.or(lower.config),
+            priority: self.priority.or(lower.priority),
+            build_env: self.build_env.or(lower.build_env),
+            rustup_default: self.rustup_default.or(lower.rustup_default),
+            copy_back: self.copy_back.or(lower.copy_back),
+            no_copy_lock: self.no_copy_lock.or(lower.no_copy_lock),
+            transfer_hidden: self.transfer_hidden.or(lower.transfer_hidden),
+            no_gitignore: self.no_gitignore.or(lower.no_gitignore),
+            ready_timeout: self.ready_timeout.or(lower.ready_timeout),
+            exclude,
+        }
+    }
+
+    /// Fills everything that wasn't given on the command line
+    pub fn apply(&self, mut exec: ExecOpts) -> ExecOpts {
+        if exec.server_id.is_none() {
+            exec.config = exec.config.or(self.config.clone());
+        }
+        exec.build_env = exec.build_env.or(self.build_env.clone());
+        exec.rustup_default = exec.rustup_default.or(self.rustup_default.clone());
+        exec.copy_back = exec.copy_back.or(self.copy_back.clone());
+        exec.ready_timeout = exec.ready_timeout.or(self.ready_timeout);
+        exec.no_copy_lock = exec.no_copy_lock.or(self.no_copy_lock);
+        exec.hidden = exec.hidden.or(self.transfer_hidden);
+        exec.no_gitignore = exec.no_gitignore.or(self.no_gitignore);
+        exec.exclude.extend(self.exclude.iter().cloned());
+        exec
+    }
+}
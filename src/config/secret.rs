@@ -0,0 +1,148 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+use inquire::{Password, Select, Text};
+use serde::{Deserialize, Serialize};
+
+/// A secret in `config.toml`: either the value itself or where to get it from, e.g.
+/// `api_key = { env = "HCLOUD_TOKEN" }` or `api_key = { command = "pass show hetzner" }`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Secret {
+    Plain(String),
+    Ref(SecretRef),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretRef {
+    /// Name of an environment variable
+    Env(String),
+    /// Path of a file containing the secret, `~/` is expanded
+    File(String),
+    /// Shell command printing the secret to stdout
+    Command(String),
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Secret::Plain(_) => write!(f, "Plain(<redacted>)"),
+            Secret::Ref(r) => write!(f, "{:?}", r),
+        }
+    }
+}
+
+impl fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretRef::Env(name) => write!(f, "environment variable `{}`", name),
+            SecretRef::File(path) => write!(f, "file `{}`", path),
+            SecretRef::Command(cmd) => write!(f, "command `{}`", cmd),
+        }
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl Secret {
+    /// The secret value. References are looked up on every call
+    pub fn resolve(&self) -> Result<String> {
+        let r = match self {
+            Secret::Plain(v) => return Ok(v.clone()),
+            Secret::Ref(r) => r,
+        };
+        let value = match r {
+            SecretRef::Env(name) => std::env::var(name).map_err(|_| anyhow!("{} is not set", r))?,
+            SecretRef::File(path) => std::fs::read_to_string(expand_home(path))
+                .with_context(|| format!("could not read {}", r))?,
+            SecretRef::Command(cmd) => {
+                // stdin / stderr stay attached, e.g. for a gpg pinentry
+                let out = Command::new("sh")
+                    .arg("-c")
+                    .arg(cmd)
+                    .stdin(Stdio::inherit())
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .with_context(|| format!("could not run {}", r))?;
+                if !out.status.success() {
+                    return Err(anyhow!("{} failed ({})", r, out.status));
+                }
+                // `pass` prints additional lines after the password
+                String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string()
+            }
+        };
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return Err(anyhow!("{} is empty", r));
+        }
+        Ok(value)
+    }
+
+    /// For `config show`: literal values are hidden, references are shown as they are
+    pub fn redacted(&self) -> Secret {
+        match self {
+            Secret::Plain(_) => Secret::Plain("<redacted>".into()),
+            Secret::Ref(r) => Secret::Ref(r.clone()),
+        }
+    }
+}
+
+const STORE_PLAIN: &str = "In config.toml (plaintext)";
+const STORE_ENV: &str = "Environment variable";
+const STORE_FILE: &str = "File";
+const STORE_COMMAND: &str = "Command printing it (e.g. `pass show ...`)";
+
+/// Asks for a secret and how to store it. Returns what goes into the config and the value
+pub fn secret_wizzard(
+    what: &str,
+    env_example: &str,
+    command_example: &str,
+) -> Result<(Secret, String)> {
+    let how = Select::new(
+        &format!("How should the {} be stored?", what),
+        vec![STORE_PLAIN, STORE_ENV, STORE_FILE, STORE_COMMAND],
+    )
+    .prompt()?;
+
+    let secret = match how {
+        STORE_PLAIN => {
+            let value = Password::new(what).without_confirmation().prompt()?;
+            return Ok((Secret::Plain(value.clone()), value));
+        }
+        STORE_ENV => Secret::Ref(SecretRef::Env(
+            Text::new("Environment variable:")
+                .with_initial_value(env_example)
+                .prompt()?,
+        )),
+        STORE_FILE => Secret::Ref(SecretRef::File(Text::new("File path:").prompt()?)),
+        _ => Secret::Ref(SecretRef::Command(
+            Text::new("Command:")
+                .with_initial_value(command_example)
+                .prompt()?,
+        )),
+    };
+    match secret.resolve() {
+        Ok(value) => Ok((secret, value)),
+        Err(e) => {
+            // The reference is saved anyway, it may only be available later (e.g. in CI)
+            eprintln!(
+                "***** {:#}. The {} is needed now to query the provider *****",
+                e, what
+            );
+            let value = Password::new(what).without_confirmation().prompt()?;
+            Ok((secret, value))
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, info};
+
+use crate::config::mode::Mode;
+use crate::config::{RemoteHost, SavedConfigs};
+use crate::core::{
+    open_session, project_key_from_dir, resolve_project, run_session, ssh_target, Stage,
+};
+use crate::provider::handle::{ServerHandle, ServerState};
+use crate::provider::{check_guards, get_provider, Provider, RentOptions};
+use crate::state::State;
+use crate::ExecOpts;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Fails if Ctrl-C was pressed while an ephemeral Server is alive
+pub fn check_interrupted() -> Result<()> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        return Err(anyhow!("interrupted"));
+    }
+    Ok(())
+}
+
+/// Deletes ephemeral Servers on Ctrl-C instead of leaving them behind
+pub fn cleanup_on_interrupt() -> Result<()> {
+    // Ctrl-C also reaches ssh/rsync, we only have to make sure to clean up afterwards
+    ctrlc::set_handler(|| {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        eprintln!("Interrupted, deleting the ephemeral server...");
+    })?;
+    Ok(())
+}
+
+/// A rented Server that is deleted again when dropped
+pub struct EphemeralServer {
+    provider: Box<dyn Provider>,
+    pub handle: ServerHandle,
+}
+
+impl EphemeralServer {
+    pub fn rent(
+        provider: Box<dyn Provider>,
+        project_key: &str,
+        opts: &RentOptions,
+    ) -> Result<Self> {
+        let mut handle = provider.rent(project_key, opts)?;
+        handle.state = ServerState::Ephemeral;
+        info!("Rented ephemeral server {} (id={})", handle.host, handle.id);
+        // From here on the Server is deleted on every error
+        let server = Self { provider, handle };
+
+        // Tracked so `cargo remote end` can clean up if we get killed, builds don't select it
+        let mut st = State::load().unwrap_or_default();
+        st.projects.push(server.handle.clone());
+        st.save()?;
+
+        Ok(server)
+    }
+}
+
+impl Drop for EphemeralServer {
+    fn drop(&mut self) {
+        info!("Deleting ephemeral server {}", self.handle.host);
+        if let Err(e) = self.provider.delete(&self.handle) {
+            error!(
+                "could not delete server id={}: {:#}. Delete it with `cargo remote end`",
+                self.handle.id, e
+            );
+            return;
+        }
+        let mut st = State::load().unwrap_or_default();
+        st.remove(&self.handle.id);
+        if let Err(e) = st.save() {
+            error!("{:#}", e);
+        }
+    }
+}
+
+/// Runs a remote command on a Server that only lives for this command
+pub fn ephemeral_exec(exec: ExecOpts, cmd: &str, options: Vec<String>) -> Result<i32> {
+    let (project_dir, _, exec) = resolve_project(exec).context(Stage::Config)?;
+    let key = project_key_from_dir(&project_dir);
+
+    let cfgs = SavedConfigs::load()?;
+    let name = exec
+        .config
+        .clone()
+        .or(cfgs.default.clone())
+        .ok_or_else(|| anyhow!("--ephemeral needs --config <name> of a cloud config"))
+        .context(Stage::Config)?;
+    let cfg = cfgs
+        .get(&name)
+        .filter(|c| Mode::check_cloud_mode(&c.mode))
+        .ok_or_else(|| anyhow!("`{}` is not a cloud config", name))
+        .context(Stage::Config)?;
+    let provider = get_provider(&cfg).context(Stage::Config)?;
+    check_guards(&cfg, provider.as_ref(), &State::load().unwrap_or_default())
+        .context(Stage::Config)?;
+
+    cleanup_on_interrupt().context(Stage::Config)?;
+
+    let server =
+        EphemeralServer::rent(provider, &key, &RentOptions::default()).context(Stage::Provider)?;
+    let remote = RemoteHost {
+        host: server.handle.host.clone(),
+        user: server.handle.username.clone(),
+        port: server.handle.port,
+        ssh_key: cfg.private_key_path().into(),
+        handle: Some(server.handle.clone()),
+    };
+
+    let transport = Arc::new(ssh_target(&remote, &exec)?);
+    let s = open_session(project_dir, exec, transport, true, cmd, options)?;
+    run_session(&s)
+}
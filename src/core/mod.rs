@@ -1,16 +1,133 @@
-use crate::config::SavedConfigs;
-use anyhow::{anyhow, Result};
+use crate::config::project::ProjectConfig;
+use crate::config::{RemoteHost, SavedConfigs};
+use crate::provider::cloud_init::READY_CHECK;
+use crate::provider::handle::ServerHandle;
+use crate::provider::Provider;
+use anyhow::{anyhow, Context, Result};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-use std::path::PathBuf;
-use std::process::{Command, Output, Stdio};
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{error, fmt};
 
 use crate::{ExecOpts, SessionRemote};
 
-pub fn metadata_dir(manifest_path: PathBuf) -> Result<PathBuf> {
+pub mod ephemeral;
+pub mod ssh;
+pub mod transport;
+
+pub use ephemeral::{check_interrupted, ephemeral_exec};
+pub use ssh::SshTarget;
+pub use transport::{LocalTransport, Transport};
+
+/// Hidden files that affect the build and are synced even without `--transfer-hidden`.
+/// Everything else hidden (`.git`, `.env`, ...) stays local.
+const BUILD_DOTFILES: [&str; 6] = [
+    "--include=.cargo/",
+    "--include=.cargo/config",
+    "--include=.cargo/config.toml",
+    "--exclude=.cargo/*",
+    "--include=.rustfmt.toml",
+    "--include=.clippy.toml",
+];
+
+/// Seconds to wait for a cloud Server to finish provisioning
+pub const DEFAULT_READY_TIMEOUT: u64 = 900;
+
+/// Per-project rsync rules (`pattern` excludes, `!pattern` includes)
+pub const IGNORE_FILE: &str = ".cargoremoteignore";
+
+/// The phase of a remote command that failed. Attached as context to errors, so that the
+/// process can exit with a code that doesn't collide with the exit code of the remote cargo.
+///
+/// | Code    | Meaning                                              |
+/// |---------|------------------------------------------------------|
+/// | 0-249   | exit code of the remote cargo (128+N for signal N)   |
+/// | 250     | local configuration / host selection failed          |
+/// | 251     | syncing files (rsync) failed                         |
+/// | 252     | cloud Server failed / not ready in time              |
+/// | 255     | ssh failed (same as `ssh` itself)                    |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Config,
+    Sync,
+    Provider,
+    Ssh,
+}
+
+impl Stage {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Stage::Config => 250,
+            Stage::Sync => 251,
+            Stage::Provider => 252,
+            Stage::Ssh => 255,
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Stage::Config => "configuration error",
+            Stage::Sync => "sync failed",
+            Stage::Provider => "cloud server failed",
+            Stage::Ssh => "ssh failed",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl error::Error for Stage {}
+
+/// Maps an error of `remote_exec` to the process exit code (see [`Stage`])
+pub fn exit_code(e: &anyhow::Error) -> i32 {
+    e.downcast_ref::<Stage>()
+        .map(Stage::exit_code)
+        .unwrap_or(Stage::Config.exit_code())
+}
+
+/// Exit code of a process, using the shell convention 128+N for signal N
+pub fn status_code(status: &ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|s| 128 + s))
+        .unwrap_or(1)
+}
+
+/// Parses durations like `90`, `90s`, `30m`, `2h` or `1d`
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: u64 = num
+        .parse()
+        .map_err(|_| anyhow!("invalid duration `{}`", s))?;
+    let secs = match unit {
+        "s" => n,
+        "m" => n * 60,
+        "h" => n * 60 * 60,
+        "d" => n * 60 * 60 * 24,
+        _ => {
+            return Err(anyhow!(
+                "invalid duration unit in `{}` (use s, m, h or d)",
+                s
+            ))
+        }
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+pub fn project_metadata(manifest_path: PathBuf) -> Result<cargo_metadata::Metadata> {
     let mut m = cargo_metadata::MetadataCommand::new();
     m.manifest_path(manifest_path).no_deps();
-    Ok(m.exec()?.workspace_root.into_std_path_buf())
+    Ok(m.exec()?)
 }
 
 pub fn project_key_from_dir(dir: &PathBuf) -> String {
@@ -32,151 +149,234 @@ pub fn sh_quote(s: &str) -> String {
     out
 }
 
-pub fn remote_home(ssh_key: &PathBuf, ssh_port: u16, server_ssh: &str) -> Result<String> {
-    let out = Command::new("ssh")
-        .arg("-i")
-        .arg(ssh_key)
-        .arg("-p")
-        .arg(ssh_port.to_string())
-        .arg(server_ssh)
-        .arg("bash -lc 'printf %s \"$HOME\"'")
-        .output()?;
-    if !out.status.success() {
-        return Err(anyhow!("could not get remote HOME"));
+/// Resolves the project of `exec` and layers the per-project settings under the CLI flags
+pub fn resolve_project(exec: ExecOpts) -> Result<(PathBuf, ProjectConfig, ExecOpts)> {
+    let metadata = project_metadata(exec.manifest_path.clone())?;
+    let project_dir = metadata.workspace_root.clone().into_std_path_buf();
+    let project = ProjectConfig::load(&metadata)?;
+    let exec = project.apply(exec);
+    Ok((project_dir, project, exec))
+}
+
+/// Resolves project and remote Host of a remote command
+pub fn session_remote(exec: ExecOpts, cmd: &str, options: Vec<String>) -> Result<SessionRemote> {
+    // Identify project
+    let (project_dir, project, exec) = resolve_project(exec).context(Stage::Config)?;
+
+    // Resolve remote host (may prompt if the selection is ambiguous)
+    let mut cfgs = SavedConfigs::load()?;
+    cfgs.priority = project.priority.clone().or(cfgs.priority);
+    if let Some(dir) = exec.loopback.clone() {
+        let transport = Arc::new(LocalTransport { root: dir });
+        return open_session(project_dir, exec, transport, false, cmd, options);
     }
-    let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
-    if s.is_empty() {
-        return Err(anyhow!("empty remote HOME"));
+    let remote = cfgs
+        .select_remote_host(
+            exec.config.as_deref(),
+            exec.server_id.as_deref(),
+            !exec.no_prompt,
+        )
+        .context(Stage::Config)?;
+
+    let transport = Arc::new(ssh_target(&remote, &exec)?);
+    // Adopted Servers without our cloud-init recipe never get the ready marker
+    let cloud = remote.handle.as_ref().is_some_and(|h| h.ready_marker);
+    open_session(project_dir, exec, transport, cloud, cmd, options)
+}
+
+/// The ssh connection to a selected Host
+pub fn ssh_target(remote: &RemoteHost, exec: &ExecOpts) -> Result<SshTarget> {
+    let mut ssh = SshTarget::new(
+        remote.ssh_key.clone(),
+        remote.port,
+        format!("{}@{}", remote.user, remote.host),
+    );
+    if remote.handle.is_some() {
+        ssh = ssh.accept_new();
+    }
+    if !exec.no_mux {
+        ssh = ssh.multiplexed(exec.ssh_persist).context(Stage::Ssh)?;
     }
-    Ok(s)
+    Ok(ssh)
 }
 
-pub fn remote_exec(exec: ExecOpts, cmd: &str, options: Vec<String>) -> anyhow::Result<i32> {
-    // Identify project + key
-    let project_dir = metadata_dir(exec.manifest_path.clone())?;
+/// Prepares the remote build folder. `cloud` waits for a rented Server to finish provisioning.
+pub fn open_session(
+    project_dir: PathBuf,
+    exec: ExecOpts,
+    transport: Arc<dyn Transport>,
+    cloud: bool,
+    cmd: &str,
+    options: Vec<String>,
+) -> Result<SessionRemote> {
     let key = project_key_from_dir(&project_dir);
 
-    // Resolve remote host (may prompt if Priority::Ask)
-    let cfgs = SavedConfigs::load().unwrap_or_default();
-    let (host, user, ssh_port, ssh_key) = cfgs.select_remote_host()?;
-
-    let full_host = format!("{}@{}", user, host);
+    // A freshly rented Server may still be running cloud-init
+    if cloud {
+        let timeout = Duration::from_secs(exec.ready_timeout.unwrap_or(DEFAULT_READY_TIMEOUT));
+        wait_ready(transport.as_ref(), timeout).context(Stage::Provider)?;
+    }
 
     // Compute remote build path
-    let home = remote_home(&ssh_key, ssh_port, &full_host)?;
+    let home = transport.home().context(Stage::Ssh)?;
     let build_path = format!("{}/remote-builds/{}/", home, key);
 
-    // Hand off to the existing runner
-    let s = SessionRemote {
+    Ok(SessionRemote {
         project_dir,
-        server_ssh: full_host,
-        ssh_key,
-        ssh_port,
+        transport,
         build_path,
-        build_env: exec.build_env,
-        rustup_default: exec.rustup_default,
+        build_env: exec.build_env.unwrap_or_else(|| "RUST_BACKTRACE=1".into()),
+        rustup_default: exec.rustup_default.unwrap_or_else(|| "stable".into()),
         copy_back: exec.copy_back,
-        no_copy_lock: exec.no_copy_lock,
-        hidden: exec.hidden,
+        no_copy_lock: exec.no_copy_lock.unwrap_or(false),
+        hidden: exec.hidden.unwrap_or(false),
+        exclude: exec.exclude,
+        gitignore: !exec.no_gitignore.unwrap_or(false),
         command: cmd.into(),
         options,
-    };
+    })
+}
+
+pub fn remote_exec(exec: ExecOpts, cmd: &str, options: Vec<String>) -> anyhow::Result<i32> {
+    if exec.ephemeral {
+        return ephemeral_exec(exec, cmd, options);
+    }
+    let s = session_remote(exec, cmd, options)?;
+    run_session(&s)
+}
+
+/// Syncs the project, runs cargo and copies back the results. Returns the cargo exit code.
+pub fn run_session(s: &SessionRemote) -> Result<i32> {
+    upsync(s).context(Stage::Sync)?;
+    check_interrupted()?;
+    let out = run_cargo(s).context(Stage::Ssh)?;
+    let code = status_code(&out.status);
+    check_interrupted()?;
 
-    // Run the acutal Session
-    // check_redy()?;
-    upsync(&s)?;
-    let out = run_cargo(&s)?;
-    downsync(&s)?;
+    // A failed build usually has nothing to copy back, the cargo exit code is more important
+    if let Err(e) = downsync(s) {
+        if code != 0 {
+            log::warn!("{:#}", e);
+        } else {
+            return Err(e.context(Stage::Sync));
+        }
+    }
 
-    Ok(out.status.code().unwrap_or(1))
+    Ok(code)
 }
 
 pub fn upsync(s: &SessionRemote) -> Result<()> {
     // Create the remote build_folder
-    let _ = Command::new("ssh")
-        .arg("-i")
-        .arg(&s.ssh_key)
-        .arg("-p")
-        .arg(s.ssh_port.to_string())
-        .arg(&s.server_ssh)
-        .arg(format!(
+    let _ = s
+        .transport
+        .command(&format!(
             "bash -lc \"mkdir -p '{}'\"",
             s.build_path.trim_end_matches('/')
         ))
         .status()?;
 
-    // Upsync command
-    let mut rsync_cmd = Command::new("rsync");
-    rsync_cmd
+    let filters = sync_filters(&s.project_dir, s.hidden, &s.exclude, s.gitignore)?;
+    s.transport.push(&s.project_dir, &s.build_path, &filters)
+}
+
+/// Builds the rsync filter arguments for syncing the project. The first matching rule wins:
+/// `.cargoremoteignore`, then `--exclude`, then the defaults and finally `.gitignore` files.
+/// rsync reads the latter itself and ignores git's `!pattern` negations in them.
+pub fn sync_filters(
+    project_dir: &Path,
+    hidden: bool,
+    exclude: &[String],
+    gitignore: bool,
+) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+
+    let ignore = project_dir.join(IGNORE_FILE);
+    if ignore.is_file() {
+        let content = std::fs::read_to_string(&ignore)
+            .with_context(|| format!("read {}", ignore.display()))?;
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.strip_prefix('!') {
+                Some(pattern) => args.push(format!("--include={}", pattern)),
+                None => args.push(format!("--exclude={}", line)),
+            }
+        }
+    }
+
+    for e in exclude {
+        args.push(format!("--exclude={}", e));
+    }
+    args.push("--exclude=target".into());
+    if !hidden {
+        for rule in BUILD_DOTFILES {
+            args.push(rule.to_string());
+        }
+        args.push("--exclude=.*".into());
+    }
+    if gitignore {
+        args.push("--filter=:- .gitignore".into());
+    }
+    Ok(args)
+}
+
+/// Lists the files that would be synced to the remote Host and returns their total size
+pub fn sync_dry_run(exec: ExecOpts) -> Result<u64> {
+    let (project_dir, _, exec) = resolve_project(exec)?;
+    let filters = sync_filters(
+        &project_dir,
+        exec.hidden.unwrap_or(false),
+        &exec.exclude,
+        !exec.no_gitignore.unwrap_or(false),
+    )?;
+
+    // Comparing against a directory that doesn't exist lists every file
+    let empty = std::env::temp_dir().join(format!("cargo-remote-dry-run-{}", std::process::id()));
+    let out = Command::new("rsync")
         .arg("-a")
-        .arg("--delete")
-        .arg("--compress")
-        .arg("-e")
-        .arg(format!(
-            "ssh -i {} -p {}",
-            s.ssh_key.to_string_lossy(),
-            s.ssh_port
-        ))
-        .arg("--exclude")
-        .arg("target");
-    if !s.hidden {
-        rsync_cmd
-            .arg("--exclude")
-            .arg(".*")
-            .arg("--exclude")
-            .arg("*/.*");
-    }
-    rsync_cmd
-        .arg(format!("{}/", s.project_dir.to_string_lossy()))
-        .arg(format!("{}:{}", s.server_ssh, s.build_path));
-    let status = rsync_cmd.status()?;
-    if !status.success() {
-        return Err(anyhow!("rsync failed"));
+        .arg("--dry-run")
+        .arg("--out-format=%l %n")
+        .args(filters)
+        .arg(format!("{}/", project_dir.to_string_lossy()))
+        .arg(&empty)
+        .output()
+        .context("could not run rsync")?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "rsync failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
     }
-    Ok(())
-    // Upsync done!
+
+    let mut total = 0;
+    for line in String::from_utf8_lossy(&out.stdout).lines() {
+        let Some((size, name)) = line.split_once(' ') else {
+            continue;
+        };
+        if name.ends_with('/') {
+            continue;
+        }
+        let size: u64 = size.parse().unwrap_or(0);
+        total += size;
+        println!("{:>12} {}", size, name);
+    }
+    Ok(total)
 }
 
 pub fn downsync(s: &SessionRemote) -> Result<()> {
     // Copy Back
     if let Some(name) = &s.copy_back {
-        let status = Command::new("rsync")
-            .arg("-a")
-            .arg("--compress")
-            .arg("-e")
-            .arg(format!(
-                "ssh -i {} -p {}",
-                s.ssh_key.to_string_lossy(),
-                s.ssh_port
-            ))
-            .arg(format!(
-                "{}:'{}/target/{}/'",
-                s.server_ssh,
-                s.build_path.trim_end_matches('/'),
-                name
-            ))
-            .arg(format!(
-                "{}/target/{}/",
-                s.project_dir.to_string_lossy(),
-                name
-            ))
-            .status()?;
-        if !status.success() {
-            return Err(anyhow!("rsync copy-back failed"));
-        }
+        s.transport.pull(
+            &format!("{}/target/{}/", s.build_path.trim_end_matches('/'), name),
+            &s.project_dir.join("target").join(name),
+        )?;
     }
     if !s.no_copy_lock {
-        let _ = Command::new("rsync")
-            .arg("-a")
-            .arg("-e")
-            .arg(format!(
-                "ssh -i {} -p {}",
-                s.ssh_key.to_string_lossy(),
-                s.ssh_port
-            ))
-            .arg(format!("{}:'{}Cargo.lock'", s.server_ssh, s.build_path))
-            .arg(format!("{}/Cargo.lock", s.project_dir.to_string_lossy()))
-            .status()?;
+        let _ = s.transport.pull(
+            &format!("{}Cargo.lock", s.build_path),
+            &s.project_dir.join("Cargo.lock"),
+        );
     }
     Ok(())
 }
@@ -188,17 +388,28 @@ pub fn run_cargo(s: &SessionRemote) -> Result<Output> {
         .map(|x| sh_quote(x))
         .collect::<Vec<_>>()
         .join(" ");
+    // Locally `rustup default` would switch the developer's toolchain, select it per command
+    let (setup, toolchain) = if s.transport.is_local() {
+        (
+            String::new(),
+            format!("RUSTUP_TOOLCHAIN={}", s.rustup_default),
+        )
+    } else {
+        (
+            format!(
+                "rustup default {} >/dev/null 2>&1 || true;",
+                s.rustup_default
+            ),
+            String::new(),
+        )
+    };
     let cmd = format!(
-        "bash -lc \"cd '{}' && {} rustup default {} >/dev/null 2>&1 || true; {} cargo {} {}\"",
-        s.build_path, "", s.rustup_default, s.build_env, s.command, quoted_opts
+        "bash -lc \"cd '{}' && {} {} {} cargo {} {}; exit \\$?\"",
+        s.build_path, setup, toolchain, s.build_env, s.command, quoted_opts
     );
-    let out = Command::new("ssh")
-        .arg("-i")
-        .arg(&s.ssh_key)
-        .arg("-p")
-        .arg(s.ssh_port.to_string())
-        .arg(&s.server_ssh)
-        .arg(cmd)
+    let out = s
+        .transport
+        .command(&cmd)
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
         .stdin(Stdio::inherit())
@@ -207,30 +418,158 @@ pub fn run_cargo(s: &SessionRemote) -> Result<Output> {
     Ok(out)
 }
 
-#[allow(dead_code)]
-pub fn check_ready(s: &SessionRemote) -> Result<()> {
-    let check_cmd = "sh -lc 'test -f /root/ready'".to_string();
-    let ready = Command::new("ssh")
-        .arg("-i")
-        .arg(&s.ssh_key)
-        .arg("-p")
-        .arg(s.ssh_port.to_string())
-        .arg(&s.server_ssh)
-        .arg(&check_cmd)
-        .status()?
-        .success();
-    if !ready {
-        let ci = Command::new("ssh")
-            .arg("-i")
-            .arg(&s.ssh_key)
-            .arg("-p")
-            .arg(s.ssh_port.to_string())
-            .arg(&s.server_ssh)
-            .arg("cloud-init status --long || true")
-            .output()?;
-        let msg = String::from_utf8_lossy(&ci.stdout);
-        return Err(anyhow!(format!("cloud-init not ready\n{}", msg.trim())));
+enum ReadyState {
+    Ready,
+    /// Still provisioning or not reachable yet, with the last cloud-init / ssh output
+    Pending(String),
+    Failed(String),
+}
+
+fn ready_state(ssh: &dyn Transport) -> Result<ReadyState> {
+    let out = ssh
+        .command(&format!(
+            "sh -lc '{{ {}; }} && echo ready || cloud-init status --long'",
+            READY_CHECK
+        ))
+        .stdin(Stdio::null())
+        .output()?;
+    let stdout = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    if out.status.code() == Some(255) {
+        return Ok(ReadyState::Pending(
+            String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        ));
     }
+    if stdout == "ready" {
+        Ok(ReadyState::Ready)
+    } else if stdout.contains("status: error") {
+        Ok(ReadyState::Failed(stdout))
+    } else if stdout.contains("status: done") {
+        // cloud-init won't run again, the marker isn't going to appear
+        Ok(ReadyState::Failed(format!(
+            "cloud-init finished without marking the server ready\n{}",
+            stdout
+        )))
+    } else {
+        Ok(ReadyState::Pending(stdout))
+    }
+}
 
-    Ok(())
+/// Waits until a rented Server finished provisioning (the ready marker exists)
+pub fn wait_ready(ssh: &dyn Transport, timeout: Duration) -> Result<()> {
+    let start = Instant::now();
+    let mut waiting = false;
+    loop {
+        let last = match ready_state(ssh)? {
+            ReadyState::Ready => {
+                if waiting {
+                    eprintln!();
+                }
+                return Ok(());
+            }
+            ReadyState::Failed(msg) => {
+                if waiting {
+                    eprintln!();
+                }
+                return Err(anyhow!("cloud-init failed\n{}", msg));
+            }
+            ReadyState::Pending(msg) => msg,
+        };
+        check_interrupted()?;
+
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            if waiting {
+                eprintln!();
+            }
+            return Err(anyhow!(
+                "server not ready after {}s\n{}",
+                timeout.as_secs(),
+                last
+            ));
+        }
+        waiting = true;
+        eprint!(
+            "\rWaiting for {} to finish provisioning ({}s/{}s)",
+            ssh.name(),
+            elapsed.as_secs(),
+            timeout.as_secs()
+        );
+        let _ = std::io::stderr().flush();
+        std::thread::sleep(Duration::from_secs(5));
+    }
+}
+
+/// Waits until the provider reports the Server as `running`
+pub fn wait_running(
+    provider: &dyn Provider,
+    handle: &ServerHandle,
+    timeout: Duration,
+) -> Result<()> {
+    let start = Instant::now();
+    loop {
+        let status = provider.status(handle)?;
+        if status == "running" {
+            return Ok(());
+        }
+        check_interrupted()?;
+        if start.elapsed() >= timeout {
+            return Err(anyhow!(
+                "server still `{}` after {}s",
+                status,
+                timeout.as_secs()
+            ));
+        }
+        std::thread::sleep(Duration::from_secs(3));
+    }
+}
+
+/// Waits until the Server accepts ssh connections
+pub fn wait_ssh(ssh: &dyn Transport, timeout: Duration) -> Result<()> {
+    let start = Instant::now();
+    loop {
+        let out = ssh.command("true").stdin(Stdio::null()).output()?;
+        if out.status.success() {
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(anyhow!(
+                "ssh not reachable after {}s\n{}",
+                timeout.as_secs(),
+                String::from_utf8_lossy(&out.stderr).trim()
+            ));
+        }
+        std::thread::sleep(Duration::from_secs(3));
+    }
+}
+
+/// Streams the cloud-init log of the Server until the ready marker exists.
+/// Fails with the end of the log if cloud-init fails or finishes without marking the Server ready.
+pub fn follow_provisioning(ssh: &dyn Transport) -> Result<()> {
+    let follow = format!(
+        "tail -n +1 -F /var/log/cloud-init-output.log 2>/dev/null & t=$!; \
+        until {check}; do \
+          s=$(cloud-init status 2>/dev/null); \
+          case \"$s\" in *error*|*done*) {check} || {{ kill $t; exit 3; }} ;; esac; \
+          sleep 2; \
+        done; sleep 1; kill $t",
+        check = READY_CHECK
+    );
+    let status = ssh
+        .command(&format!("sh -c {}", sh_quote(&follow)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()?;
+    if status.success() {
+        return Ok(());
+    }
+
+    let tail = ssh
+        .command("tail -n 30 /var/log/cloud-init-output.log; cloud-init status --long || true")
+        .stdin(Stdio::null())
+        .output()?;
+    Err(anyhow!(
+        "provisioning failed\n{}",
+        String::from_utf8_lossy(&tail.stdout).trim()
+    ))
 }
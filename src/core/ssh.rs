@@ -0,0 +1,213 @@
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+
+use crate::core::sh_quote;
+use crate::core::transport::{rsync, Transport};
+
+/// Seconds a multiplexed connection outlives its last use, if we don't close it ourselves
+const DEFAULT_PERSIST: u64 = 30;
+
+/// Everything needed to open an ssh connection to a remote Host
+#[derive(Debug, Clone)]
+pub struct SshTarget {
+    pub key: PathBuf,
+    pub port: u16,
+    /// `user@host`
+    pub host: String,
+    /// Accept the host key of unknown Hosts (freshly rented Servers)
+    accept_new: bool,
+    control: Option<Arc<ControlMaster>>,
+}
+
+/// A shared ssh connection (`ControlMaster`), which is closed when the last user is dropped
+/// unless it should be kept alive between invocations
+#[derive(Debug)]
+struct ControlMaster {
+    path: PathBuf,
+    persist: u64,
+    keep: bool,
+    host: String,
+    port: u16,
+}
+
+/// Directory of the control sockets: `$XDG_RUNTIME_DIR/cargo-remote-ssh`, otherwise one per user
+/// in the temp dir. An existing one is only used if it belongs to this user
+fn control_dir() -> Result<PathBuf> {
+    // SAFETY: `getuid` has no preconditions and can't fail
+    let uid = unsafe { libc::getuid() };
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        Some(runtime) => PathBuf::from(runtime).join("cargo-remote-ssh"),
+        None => std::env::temp_dir().join(format!("cargo-remote-ssh-{}", uid)),
+    };
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(e) if e.kind() != ErrorKind::AlreadyExists => {
+            return Err(e).with_context(|| format!("could not create {}", dir.display()));
+        }
+        _ => {}
+    }
+    let meta = std::fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() || meta.uid() != uid {
+        return Err(anyhow!("{} is not a directory of this user", dir.display()));
+    }
+    if meta.mode() & 0o077 != 0 {
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+impl SshTarget {
+    pub fn new(key: PathBuf, port: u16, host: String) -> Self {
+        Self {
+            key,
+            port,
+            host,
+            accept_new: false,
+            control: None,
+        }
+    }
+
+    pub fn accept_new(mut self) -> Self {
+        self.accept_new = true;
+        self
+    }
+
+    /// Reuses a single connection for all ssh / rsync calls. With `persist` the connection stays
+    /// open that long after this process exits, so following invocations skip the handshake.
+    pub fn multiplexed(mut self, persist: Option<Duration>) -> Result<Self> {
+        let dir = match control_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                warn!("not reusing ssh connections: {:#}", e);
+                return Ok(self);
+            }
+        };
+
+        self.control = Some(Arc::new(ControlMaster {
+            path: dir.join("%C"),
+            persist: persist.map(|d| d.as_secs()).unwrap_or(DEFAULT_PERSIST),
+            keep: persist.is_some(),
+            host: self.host.clone(),
+            port: self.port,
+        }));
+        Ok(self)
+    }
+
+    fn options(&self) -> Vec<String> {
+        let mut args = vec![
+            "-i".to_string(),
+            self.key.to_string_lossy().to_string(),
+            "-p".to_string(),
+            self.port.to_string(),
+            "-o".to_string(),
+            "ConnectTimeout=10".to_string(),
+        ];
+        if self.accept_new {
+            args.push("-o".into());
+            args.push("StrictHostKeyChecking=accept-new".into());
+        }
+        if let Some(c) = &self.control {
+            args.push("-o".into());
+            args.push("ControlMaster=auto".into());
+            args.push("-o".into());
+            args.push(format!("ControlPath={}", c.path.to_string_lossy()));
+            args.push("-o".into());
+            args.push(format!("ControlPersist={}", c.persist));
+        }
+        args
+    }
+
+    /// `ssh <options> user@host <remote_cmd>`
+    pub fn command(&self, remote_cmd: &str) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.args(self.options()).arg(&self.host).arg(remote_cmd);
+        cmd
+    }
+
+    /// The remote shell for `rsync -e`
+    pub fn rsh(&self) -> String {
+        let mut s = String::from("ssh");
+        for o in self.options() {
+            s.push(' ');
+            s.push_str(&sh_quote(&o));
+        }
+        s
+    }
+
+    /// `user@host:path` for rsync
+    pub fn remote_path(&self, path: &str) -> String {
+        format!("{}:{}", self.host, path)
+    }
+}
+
+impl Transport for SshTarget {
+    fn name(&self) -> String {
+        self.host.clone()
+    }
+
+    fn home(&self) -> Result<String> {
+        let out = self.command("bash -lc 'printf %s \"$HOME\"'").output()?;
+        if !out.status.success() {
+            return Err(anyhow!("could not get remote HOME"));
+        }
+        let s = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        if s.is_empty() {
+            return Err(anyhow!("empty remote HOME"));
+        }
+        Ok(s)
+    }
+
+    fn command(&self, cmd: &str) -> Command {
+        SshTarget::command(self, cmd)
+    }
+
+    fn push(&self, from: &Path, to: &str, filters: &[String]) -> Result<()> {
+        let status = rsync(Some(self.rsh()))
+            .arg("--delete")
+            .args(filters)
+            .arg(format!("{}/", from.to_string_lossy()))
+            .arg(self.remote_path(to))
+            .status()?;
+        if !status.success() {
+            return Err(anyhow!("rsync failed"));
+        }
+        Ok(())
+    }
+
+    fn pull(&self, from: &str, to: &Path) -> Result<()> {
+        let status = rsync(Some(self.rsh()))
+            .arg(self.remote_path(&sh_quote(from)))
+            .arg(to)
+            .status()?;
+        if !status.success() {
+            return Err(anyhow!("rsync copy-back failed"));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ControlMaster {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        let _ = Command::new("ssh")
+            .arg("-o")
+            .arg(format!("ControlPath={}", self.path.to_string_lossy()))
+            .arg("-p")
+            .arg(self.port.to_string())
+            .arg("-O")
+            .arg("exit")
+            .arg(&self.host)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
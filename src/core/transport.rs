@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+
+/// How the build Host is reached. All remote work of `core` goes through this
+pub trait Transport {
+    /// Human readable target, e.g. `root@1.2.3.4`
+    fn name(&self) -> String;
+    /// Home directory on the build Host
+    fn home(&self) -> Result<String>;
+    /// A shell command on the build Host. The caller configures stdio
+    fn command(&self, cmd: &str) -> Command;
+    /// Mirrors the local directory `from` to `to` on the build Host, using the rsync `filters`
+    fn push(&self, from: &Path, to: &str, filters: &[String]) -> Result<()>;
+    /// Copies `from` (a file, or a directory with a trailing `/`) from the build Host to `to`
+    fn pull(&self, from: &str, to: &Path) -> Result<()>;
+    /// The build Host is this machine, its global settings (e.g. `rustup default`) aren't ours
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// `rsync -a` with an optional remote shell
+pub fn rsync(rsh: Option<String>) -> Command {
+    let mut cmd = Command::new("rsync");
+    cmd.arg("-a");
+    if let Some(rsh) = rsh {
+        cmd.arg("--compress").arg("-e").arg(rsh);
+    }
+    cmd
+}
+
+/// Loopback "remote": builds happen in a local directory. Useful for testing the whole
+/// pipeline and for trying out sync settings without a server.
+pub struct LocalTransport {
+    pub root: PathBuf,
+}
+
+impl Transport for LocalTransport {
+    fn name(&self) -> String {
+        format!("local:{}", self.root.to_string_lossy())
+    }
+
+    fn home(&self) -> Result<String> {
+        std::fs::create_dir_all(&self.root)?;
+        Ok(std::fs::canonicalize(&self.root)?
+            .to_string_lossy()
+            .to_string())
+    }
+
+    fn command(&self, cmd: &str) -> Command {
+        let mut c = Command::new("sh");
+        c.arg("-c").arg(cmd);
+        c
+    }
+
+    fn push(&self, from: &Path, to: &str, filters: &[String]) -> Result<()> {
+        let status = rsync(None)
+            .arg("--delete")
+            .args(filters)
+            .arg(format!("{}/", from.to_string_lossy()))
+            .arg(to)
+            .status()?;
+        if !status.success() {
+            return Err(anyhow!("rsync failed"));
+        }
+        Ok(())
+    }
+
+    fn pull(&self, from: &str, to: &Path) -> Result<()> {
+        let status = rsync(None).arg(from).arg(to).status()?;
+        if !status.success() {
+            return Err(anyhow!("rsync copy-back failed"));
+        }
+        Ok(())
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
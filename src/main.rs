@@ -3,6 +3,7 @@ use log::{error, LevelFilter};
 use simple_logger::SimpleLogger;
 use std::path::PathBuf;
 use std::process::exit;
+use std::time::Duration;
 
 mod cmds;
 mod config;
@@ -10,17 +11,24 @@ mod core;
 mod provider;
 mod state;
 
+use crate::cmds::cache::CacheCmd;
 use crate::cmds::config::delete::config_delete;
 use crate::cmds::config::edit::config_edit;
 use crate::cmds::config::list::config_list;
 use crate::cmds::config::show::config_show;
 use crate::cmds::config::ConfigCmd;
 use crate::cmds::configure::configure_wizard;
+use crate::cmds::image::ImageCmd;
 use crate::cmds::remote::build::cmd_build;
 use crate::cmds::remote::clean::cmd_clean;
 use crate::cmds::remote::run::cmd_run;
+use crate::cmds::remote::sync::cmd_sync;
+use crate::cmds::session::adopt::adopt_session;
 use crate::cmds::session::begin::begin_session;
 use crate::cmds::session::end::end_session;
+use crate::cmds::session::pause::{pause_session, resume_session};
+use crate::core::{parse_duration, DEFAULT_READY_TIMEOUT};
+use crate::provider::IdleAction;
 
 #[derive(Args, Debug)]
 pub struct BeginOpts {
@@ -30,33 +38,117 @@ pub struct BeginOpts {
 
     #[arg(long = "preinstall", value_delimiter = ',')]
     preinstall: Vec<String>,
+
+    #[arg(long = "wait")]
+    /// Wait until the Server is provisioned and stream the cloud-init log
+    wait: bool,
+
+    #[arg(long = "timeout", value_name = "SECONDS", default_value_t = DEFAULT_READY_TIMEOUT)]
+    /// How long `--wait` waits for the Server to start and accept ssh connections
+    timeout: u64,
+
+    #[arg(long = "idle-timeout", value_parser = parse_duration)]
+    /// Shut the Server down after this time without builds (e.g. 30m, 2h)
+    idle_timeout: Option<Duration>,
+
+    #[arg(long = "idle-action", value_enum, default_value_t = IdleAction::Poweroff)]
+    /// What happens after --idle-timeout. Nothing on the Server can delete it: a powered off
+    /// Server is still billed until `cargo remote status` (poweroff-then-reap) or `end` deletes it
+    idle_action: IdleAction,
+}
+
+#[derive(Args, Debug)]
+pub struct AdoptOpts {
+    #[arg(long = "config")]
+    /// The cloud config whose account owns the Server
+    config: String,
+
+    /// Id or name of the Server
+    server: String,
+
+    #[arg(long = "check-ready")]
+    /// The Server runs the cloud-init of `begin`: wait until it is provisioned
+    /// (`/var/lib/cargo-remote/ready` exists) before adopting it and before builds. Without it builds never wait
+    check_ready: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SessionOpts {
+    #[arg(long = "config")]
+    /// Only sessions of this config
+    config: Option<String>,
+
+    #[arg(long = "server-id")]
+    /// The session with this server id
+    server_id: Option<String>,
 }
 
 #[derive(Args, Debug)]
 pub struct ExecOpts {
-    #[arg(short = 'b', long = "build-env", default_value = "RUST_BACKTRACE=1")]
-    /// Provide a builld-environment. For example RUST_BACKTRACE=1
-    build_env: String,
+    #[arg(short = 'b', long = "build-env")]
+    /// Provide a builld-environment (default RUST_BACKTRACE=1)
+    build_env: Option<String>,
 
-    #[arg(short = 'd', long = "rustup-default", default_value = "stable")]
-    /// Rustip default channel (eg. stable, nightly)
-    rustup_default: String,
+    #[arg(short = 'd', long = "rustup-default")]
+    /// Rustip default channel (eg. stable, nightly) (default stable)
+    rustup_default: Option<String>,
 
     #[arg(short = 'c', long = "copy-back")]
     /// Copy back the target folder after running / compiling
     copy_back: Option<String>,
 
-    #[arg(long = "no-copy-lock")]
-    /// If set, Cargo.lock wont be copied back
-    no_copy_lock: bool,
+    #[arg(long = "no-copy-lock", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    /// If set, Cargo.lock wont be copied back (`--no-copy-lock=false` overrides the project setting)
+    no_copy_lock: Option<bool>,
 
     #[arg(long = "manifest-path", default_value = "Cargo.toml")]
     /// Manifest path (default Cargo.toml)
     manifest_path: PathBuf,
 
-    #[arg(long = "transfer-hidden")]
-    /// Transfer hidden files (eg. .env .gitignore)
-    hidden: bool,
+    #[arg(long = "transfer-hidden", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    /// Transfer all hidden files (eg. .env .git). Build-relevant ones like .cargo/config.toml are always transferred
+    hidden: Option<bool>,
+
+    #[arg(long = "exclude")]
+    /// Additional rsync exclude pattern (can be repeated)
+    exclude: Vec<String>,
+
+    #[arg(long = "no-gitignore", num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    /// Also transfer files ignored by .gitignore. It is read by rsync, which doesn't support
+    /// `!pattern` negations: re-include such files in .cargoremoteignore
+    no_gitignore: Option<bool>,
+
+    #[arg(long = "config")]
+    /// The config name of the remote Host. Otherwise the default / priority will be used
+    config: Option<String>,
+
+    #[arg(long = "server-id")]
+    /// The id of an active cloud Server (see `cargo remote status`)
+    server_id: Option<String>,
+
+    #[arg(long = "ready-timeout", value_name = "SECONDS")]
+    /// How long to wait for a cloud Server to finish provisioning (default 900)
+    ready_timeout: Option<u64>,
+
+    #[arg(long = "loopback", value_name = "DIR", conflicts_with_all = ["config", "server_id"])]
+    /// Don't use a remote Host, "build remotely" in a local directory instead (for testing)
+    loopback: Option<PathBuf>,
+
+    #[arg(long = "ephemeral", conflicts_with_all = ["server_id", "loopback"])]
+    /// Rent a cloud Server (--config or default) only for this command and delete it afterwards
+    ephemeral: bool,
+
+    #[arg(long = "ssh-persist", value_parser = parse_duration)]
+    /// Keep the shared ssh connection open this long (e.g. 10m), so following commands reuse it
+    ssh_persist: Option<Duration>,
+
+    #[arg(long = "no-mux", conflicts_with = "ssh_persist")]
+    /// Don't share one ssh connection between sync, cargo and copy-back
+    no_mux: bool,
+
+    #[arg(long = "no-prompt")]
+    /// Fail instead of prompting if multiple remote Hosts match (implied without a TTY)
+    no_prompt: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -81,12 +173,54 @@ enum RemoteCmd {
 
     #[command(name = "end")]
     /// Deletes a rented Cloud Server
-    End,
+    End {
+        #[command(flatten)]
+        opts: SessionOpts,
+    },
+
+    #[command(name = "pause")]
+    /// Powers a Cloud Server off, keeping its disk for the next `resume`
+    Pause {
+        #[command(flatten)]
+        opts: SessionOpts,
+    },
+
+    #[command(name = "resume")]
+    /// Powers a paused Cloud Server on again
+    Resume {
+        #[command(flatten)]
+        opts: SessionOpts,
+    },
+
+    #[command(name = "adopt")]
+    /// Tracks an existing Cloud Server as a session
+    Adopt {
+        #[command(flatten)]
+        adopt: AdoptOpts,
+    },
 
     #[command(name = "status")]
     /// Shows the Status of the Cloud Server
     Status,
 
+    #[command(name = "gc")]
+    /// Finds cloud Servers created by cargo-remote, that aren't tracked anymore
+    Gc,
+
+    #[command(name = "image")]
+    /// Manages snapshots with a preinstalled toolchain, so new Servers are ready faster
+    Image {
+        #[command(subcommand)]
+        cmd: ImageCmd,
+    },
+
+    #[command(name = "cache")]
+    /// Manages the persistent build cache volume (`target/`, `~/.cargo`) of a Hetzner config
+    Cache {
+        #[command(subcommand)]
+        cmd: CacheCmd,
+    },
+
     #[command(name = "run")]
     /// Runs the application on a remote Host (Manually configured / Cloud Server)
     Run {
@@ -104,6 +238,17 @@ enum RemoteCmd {
         options: Vec<String>,
     },
 
+    #[command(name = "sync")]
+    /// Syncs the project to the remote Host without running cargo
+    Sync {
+        #[command(flatten)]
+        exec: ExecOpts,
+
+        #[arg(long = "dry-run")]
+        /// Only list the files that would be transferred and their total size
+        dry_run: bool,
+    },
+
     #[command(name = "clean")]
     /// Cleans remote target folder on the remote Host (Manually configured / Cloud Server)
     Clean {
@@ -123,15 +268,15 @@ enum CargoCli {
 #[derive(Clone)]
 struct SessionRemote {
     project_dir: PathBuf,
-    server_ssh: String,
-    ssh_key: PathBuf,
-    ssh_port: u16,
+    transport: std::sync::Arc<dyn core::Transport>,
     build_path: String,
     build_env: String,
     rustup_default: String,
     copy_back: Option<String>,
     no_copy_lock: bool,
     hidden: bool,
+    exclude: Vec<String>,
+    gitignore: bool,
     command: String,
     options: Vec<String>,
 }
@@ -155,19 +300,23 @@ fn main() {
             RemoteCmd::Config { cmd } => match cmd {
                 ConfigCmd::List => {
                     if let Err(e) = config_list() {
-                        error!("{}", e);
+                        error!("{:#}", e);
                         exit(2)
                     }
                 }
-                ConfigCmd::Show { name, index } => {
-                    if let Err(e) = config_show(name, index) {
-                        error!("{}", e);
+                ConfigCmd::Show {
+                    name,
+                    index,
+                    effective,
+                } => {
+                    if let Err(e) = config_show(name, index, effective) {
+                        error!("{:#}", e);
                         exit(2)
                     }
                 }
                 ConfigCmd::Delete { name, index } => {
                     if let Err(e) = config_delete(name, index) {
-                        error!("{}", e);
+                        error!("{:#}", e);
                         exit(2)
                     }
                 }
@@ -180,12 +329,30 @@ fn main() {
             },
             RemoteCmd::Begin { begin } => {
                 if let Err(e) = begin_session(begin) {
-                    error!("{}", e);
+                    error!("{:#}", e);
+                    exit(3)
+                }
+            }
+            RemoteCmd::End { opts } => {
+                if let Err(e) = end_session(opts) {
+                    error!("{:#}", e);
+                    exit(3)
+                }
+            }
+            RemoteCmd::Pause { opts } => {
+                if let Err(e) = pause_session(opts) {
+                    error!("{:#}", e);
+                    exit(3)
+                }
+            }
+            RemoteCmd::Resume { opts } => {
+                if let Err(e) = resume_session(opts) {
+                    error!("{:#}", e);
                     exit(3)
                 }
             }
-            RemoteCmd::End => {
-                if let Err(e) = end_session() {
+            RemoteCmd::Adopt { adopt } => {
+                if let Err(e) = adopt_session(adopt) {
                     error!("{}", e);
                     exit(3)
                 }
@@ -196,9 +363,28 @@ fn main() {
                     exit(3)
                 }
             }
+            RemoteCmd::Gc => {
+                if let Err(e) = cmds::gc::gc() {
+                    error!("{:#}", e);
+                    exit(3)
+                }
+            }
+            RemoteCmd::Image { cmd } => {
+                if let Err(e) = cmds::image::image(cmd) {
+                    error!("{:#}", e);
+                    exit(3)
+                }
+            }
+            RemoteCmd::Cache { cmd } => {
+                if let Err(e) = cmds::cache::cache(cmd) {
+                    error!("{:#}", e);
+                    exit(3)
+                }
+            }
             RemoteCmd::Run { exec, options } => cmd_run(exec, options),
             RemoteCmd::Build { exec, options } => cmd_build(exec, options),
             RemoteCmd::Clean { exec, options } => cmd_clean(exec, options),
+            RemoteCmd::Sync { exec, dry_run } => cmd_sync(exec, dry_run),
         },
     }
 }
@@ -0,0 +1,131 @@
+use crate::provider::{IdleWatchdog, RentOptions};
+
+/// Created once the Server is provisioned, readable by every user
+pub const READY_MARKER: &str = "/var/lib/cargo-remote/ready";
+/// Shell test for the marker. Servers rented by older versions have `/root/ready` instead
+pub const READY_CHECK: &str = "[ -f /var/lib/cargo-remote/ready ] || [ -f /root/ready ]";
+
+/// The provider specific parts of the cloud-init user data
+pub struct Recipe {
+    /// The toolchain is already installed (baked image), only extra packages are added
+    pub baked: bool,
+    /// `runcmd` entries before the provisioning, e.g. mounting a volume
+    pub prelude: String,
+}
+
+/// cloud-init user data installing Rust, the build essentials and `opts.preinstall`.
+/// [`READY_MARKER`] is created once the Server is usable
+pub fn user_data(opts: &RentOptions, recipe: &Recipe) -> String {
+    if recipe.baked {
+        return baked_user_data(opts, recipe);
+    }
+    let mut s = String::from(
+        "#cloud-config\npackage_update: true\npackage_upgrade: true\npackages:\n\
+ - build-essential\n\
+ - gcc\n\
+ - make\n\
+ - musl\n\
+ - musl-tools\n\
+ - libssl-dev\n\
+ - pkg-config\n\
+ - llvm\n\
+ - clang\n\
+ - git\n\
+ - curl\n\
+ - ca-certificates\n",
+    );
+    for p in &opts.preinstall {
+        if !p.trim().is_empty() {
+            s.push_str(&format!(" - {}\n", p.trim()));
+        }
+    }
+    if let Some(idle) = &opts.idle {
+        s.push_str(&idle_watchdog(idle));
+    }
+    s.push_str("runcmd:\n");
+    s.push_str(&recipe.prelude);
+    s.push_str(
+        " - [bash, -lc, \"export DEBIAN_FRONTEND=noninteractive && apt-get update && apt-get -yq upgrade\"]
+ - [bash, -lc, \"apt-get install -yqq curl ca-certificates\"]
+ - [bash, -lc, \"curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs -o /root/rustup-init.sh\"]
+ - [bash, -lc, \"chmod +x /root/rustup-init.sh\"]
+ - [bash, -lc, \"/root/rustup-init.sh -y --profile minimal --default-toolchain stable\"]
+ - [bash, -lc, \"echo 'export PATH=\\\"$HOME/.cargo/bin:$PATH\\\"' >> /root/.bashrc\"]
+ - [bash, -lc, \"printf 'export PATH=\\\"/root/.cargo/bin:$PATH\\\"\\n' > /etc/profile.d/cargo.sh && chmod +x /etc/profile.d/cargo.sh\"]
+ - [bash, -lc, \"/root/.cargo/bin/rustc --version && /root/.cargo/bin/cargo --version\"]
+",
+    );
+    s.push_str(&mark_ready());
+    if opts.idle.is_some() {
+        s.push_str(" - [systemctl, enable, --now, cargo-remote-idle.timer]\n");
+    }
+    s
+}
+
+/// The toolchain is already part of a baked image, only extra packages are installed
+fn baked_user_data(opts: &RentOptions, recipe: &Recipe) -> String {
+    let mut s = String::from("#cloud-config\n");
+    let packages: Vec<&str> = opts
+        .preinstall
+        .iter()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if !packages.is_empty() {
+        s.push_str("package_update: true\npackages:\n");
+        for p in packages {
+            s.push_str(&format!(" - {}\n", p));
+        }
+    }
+    if let Some(idle) = &opts.idle {
+        s.push_str(&idle_watchdog(idle));
+    }
+    s.push_str("runcmd:\n");
+    s.push_str(&recipe.prelude);
+    s.push_str(&mark_ready());
+    if opts.idle.is_some() {
+        s.push_str(" - [systemctl, enable, --now, cargo-remote-idle.timer]\n");
+    }
+    s
+}
+
+fn mark_ready() -> String {
+    format!(
+        " - [bash, -lc, \"mkdir -p $(dirname {m}) && touch {m}\"]\n",
+        m = READY_MARKER
+    )
+}
+
+/// `write_files` section of a systemd timer, that shuts the Server down once
+/// nothing in `/root/remote-builds` changed and no cargo ran for `idle.timeout`.
+/// No credentials are put on the Server: `IdleAction::PoweroffThenReap` deletes it on the next
+/// `cargo remote status` (or by the provider, e.g. EC2 terminating on shutdown)
+fn idle_watchdog(idle: &IdleWatchdog) -> String {
+    format!(
+        "write_files:
+ - path: /usr/local/bin/cargo-remote-idle
+   permissions: '0755'
+   content: |
+     #!/bin/sh
+     [ -f {marker} ] || exit 0
+     pgrep -x cargo >/dev/null && exit 0
+     last=$(find /root/remote-builds {marker} -maxdepth 3 -printf '%T@\\n' 2>/dev/null | sort -n | tail -n 1 | cut -d. -f1)
+     [ $(( $(date +%s) - ${{last:-0}} )) -ge {} ] || exit 0
+     systemctl poweroff
+ - path: /etc/systemd/system/cargo-remote-idle.service
+   content: |
+     [Service]
+     Type=oneshot
+     ExecStart=/usr/local/bin/cargo-remote-idle
+ - path: /etc/systemd/system/cargo-remote-idle.timer
+   content: |
+     [Timer]
+     OnBootSec=5min
+     OnUnitActiveSec=1min
+     [Install]
+     WantedBy=timers.target
+",
+        idle.timeout.as_secs(),
+        marker = READY_MARKER
+    )
+}
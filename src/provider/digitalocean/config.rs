@@ -0,0 +1,117 @@
+use std::{fmt, process::exit};
+
+use anyhow::Result;
+use inquire::{Select, Text};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{
+        mode::{ConfigData, Mode},
+        secret::{secret_wizzard, Secret},
+        SavedConfig,
+    },
+    provider::{digitalocean::DigitalOceanProvider, CloudConfig, Guards},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigitalOceanConfig {
+    pub name: String,
+    pub api_token: Secret,
+    pub region: String, // slug, e.g. `fra1`
+    pub size: String,   // slug, e.g. `s-4vcpu-8gb`
+    pub image: String,  // slug, e.g. `ubuntu-22-04-x64`
+    pub username: Option<String>,
+    pub ssh_key: String,     // fingerprint of the key in the account
+    pub private_key: String, // local private key path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>, // defaults to the public DigitalOcean API
+    #[serde(flatten)]
+    pub guards: Guards,
+}
+
+impl CloudConfig for DigitalOceanConfig {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn private_key_path(&self) -> String {
+        self.private_key.clone()
+    }
+
+    fn guards(&self) -> Guards {
+        self.guards.clone()
+    }
+}
+
+impl fmt::Display for DigitalOceanConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DigitalOceanConfig(region: {}, size: {})",
+            self.region, self.size
+        )
+    }
+}
+
+pub fn digitalocean_config_wizzard(name: String) -> Result<SavedConfig> {
+    let (api_token_secret, api_token) = secret_wizzard(
+        "DigitalOcean API token",
+        "DIGITALOCEAN_TOKEN",
+        "pass show digitalocean",
+    )?;
+
+    let region = match DigitalOceanProvider::get_regions(api_token.clone()) {
+        Ok(regions) => Select::new("Region: ", regions).prompt()?.slug,
+        Err(e) => {
+            eprintln!("***** We could not query the Regions ({:#}). Your API token could be wrong or your Computer may not be connected to the Internet. This may cause Problems later! *****", e);
+            Text::new("Region: ").with_initial_value("fra1").prompt()?
+        }
+    };
+
+    let size = match DigitalOceanProvider::get_sizes(api_token.clone()) {
+        Ok(mut sizes) => {
+            sizes.retain(|s| s.available && s.regions.contains(&region));
+            Select::new("Size: ", sizes).prompt()?.slug
+        }
+        Err(e) => {
+            eprintln!("***** We could not query the Sizes ({:#}). Your API token could be wrong or your Computer may not be connected to the Internet. This may cause Problems later! *****", e);
+            Text::new("Size: ")
+                .with_initial_value("s-4vcpu-8gb")
+                .prompt()?
+        }
+    };
+
+    let image = Text::new("Image (This tool will use apt to install rust and other dependencies! Ubuntu recommendet):")
+        .with_initial_value("ubuntu-22-04-x64")
+        .prompt()?;
+
+    let ssh_key = if let Ok(keys) = DigitalOceanProvider::get_ssh_keys(api_token.clone()) {
+        if keys.is_empty() {
+            eprint!("You need to upload your SSH-Public Key to DigitalOcean in order to connect to the Droplet later on. Exitiing...");
+            exit(1);
+        }
+        Select::new("Select your SSH-Key: ", keys)
+            .prompt()?
+            .fingerprint
+    } else {
+        Text::new("DigitalOcean SSH-Key fingerprint:").prompt()?
+    };
+
+    let private_key = Text::new("Local SSH Private Key Path: ").prompt()?;
+
+    Ok(SavedConfig {
+        mode: Mode::DigitalOcean,
+        data: ConfigData::DigitalOcean(DigitalOceanConfig {
+            name,
+            api_token: api_token_secret,
+            region,
+            size,
+            image,
+            username: Some("root".to_string()),
+            ssh_key,
+            private_key,
+            api_url: None,
+            guards: Guards::default(),
+        }),
+    })
+}
@@ -0,0 +1,383 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::provider::cloud_init::{self, Recipe};
+use crate::provider::handle::ServerState;
+use crate::provider::http::{self, Api, Dialect};
+use crate::provider::{
+    digitalocean::config::DigitalOceanConfig, listed, Existence, Provider, ProviderKind,
+    RentOptions, ServerHandle,
+};
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+pub mod config;
+
+const DEFAULT_API_URL: &str = "https://api.digitalocean.com/v2";
+const API_URL_ENV: &str = "CARGO_REMOTE_DIGITALOCEAN_API_URL";
+const TAG: &str = "cargo-remote";
+/// How long a new Droplet may take to get its public IP
+const IP_TIMEOUT: Duration = Duration::from_secs(180);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Errors are `{"id": ..., "message": ...}`, list responses link the next page if there is one
+static DIALECT: Dialect = Dialect {
+    name: "digitalocean",
+    error: |v| {
+        Some((
+            v.get("id")?.as_str()?.to_string(),
+            v.get("message")?.as_str()?.to_string(),
+        ))
+    },
+    next_page: |v, page| v.pointer("/links/pages/next").map(|_| page + 1),
+};
+
+/// DigitalOcean tags: letters, numbers, `:`, `-` and `_`
+fn tag_value(s: &str) -> String {
+    s.trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, ':' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(200)
+        .collect()
+}
+
+pub struct DigitalOceanProvider {
+    pub cfg: DigitalOceanConfig,
+    api_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct Region {
+    pub slug: String,
+    name: String,
+    available: bool,
+}
+
+#[derive(Deserialize)]
+pub struct Size {
+    pub slug: String,
+    vcpus: u32,
+    memory: u32,
+    price_monthly: f64,
+    pub regions: Vec<String>,
+    pub available: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SSHKey {
+    name: String,
+    pub fingerprint: String,
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let available = if self.available { "" } else { ", unavailable" };
+        write!(f, "{} ({}{})", self.slug, self.name, available)
+    }
+}
+
+impl std::fmt::Display for Size {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (vCPUs: {} Ram: {} MB, {}/month)",
+            self.slug, self.vcpus, self.memory, self.price_monthly
+        )
+    }
+}
+
+impl std::fmt::Display for SSHKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.fingerprint)
+    }
+}
+
+impl DigitalOceanProvider {
+    pub fn new(cfg: DigitalOceanConfig) -> Result<Self> {
+        let api_token = cfg
+            .api_token
+            .resolve()
+            .with_context(|| format!("API token of `{}`", cfg.name))?;
+        Ok(Self { cfg, api_token })
+    }
+
+    pub fn api_url(configured: Option<&str>) -> String {
+        http::base_url(API_URL_ENV, configured, DEFAULT_API_URL)
+    }
+
+    fn client(base: String, token: &str) -> Result<Api> {
+        Api::new(base, format!("Bearer {}", token), &DIALECT)
+    }
+
+    fn base(&self) -> String {
+        Self::api_url(self.cfg.api_url.as_deref())
+    }
+
+    fn api(&self) -> Result<Api> {
+        Self::client(self.base(), &self.api_token)
+    }
+
+    fn cloud_init(&self, opts: &RentOptions) -> String {
+        cloud_init::user_data(
+            opts,
+            &Recipe {
+                baked: false,
+                prelude: String::new(),
+            },
+        )
+    }
+
+    /// Tags of rented Droplets. They have no values, so `project` and `config` are prefixes
+    fn tags(&self, project_key: &str) -> Vec<String> {
+        vec![
+            TAG.to_string(),
+            format!("{}-project:{}", TAG, tag_value(project_key)),
+            format!("{}-config:{}", TAG, tag_value(&self.cfg.name)),
+        ]
+    }
+
+    fn droplet(&self, id: &str) -> Result<Value> {
+        self.api()?
+            .get(&format!("/droplets/{}", id))?
+            .get("droplet")
+            .cloned()
+            .ok_or_else(|| anyhow!("missing droplet"))
+    }
+
+    /// Builds a handle from a droplet object of the API. Fails if it has no public IPv4 yet
+    fn handle(&self, droplet: &Value) -> Result<ServerHandle> {
+        let id = droplet
+            .get("id")
+            .and_then(|x| x.as_i64())
+            .ok_or_else(|| anyhow!("missing id"))?
+            .to_string();
+        let ip = droplet
+            .pointer("/networks/v4")
+            .and_then(|n| n.as_array())
+            .and_then(|n| {
+                n.iter()
+                    .find(|a| a.get("type").and_then(|t| t.as_str()) == Some("public"))
+            })
+            .and_then(|a| a.get("ip_address"))
+            .and_then(|x| x.as_str())
+            .ok_or_else(|| anyhow!("missing public IPv4"))?
+            .to_string();
+        let created_at = droplet
+            .get("created_at")
+            .and_then(|c| c.as_str())
+            .and_then(|c| OffsetDateTime::parse(c, &Rfc3339).ok())
+            .map(|c| c.unix_timestamp().max(0) as u64);
+        // Configs may share the account, the tag names the one that rented it
+        let own = tag_value(&self.cfg.name);
+        let config = droplet
+            .get("tags")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|t| t.as_str()?.strip_prefix(&format!("{}-config:", TAG)))
+            .find(|c| *c != own)
+            .map_or_else(|| self.cfg.name.clone(), |c| c.to_string());
+        Ok(ServerHandle {
+            provider: ProviderKind::DigitalOcean,
+            config,
+            id,
+            host: ip,
+            port: 22,
+            username: self.cfg.username.clone().unwrap_or("root".into()),
+            created_at,
+            server_type: droplet
+                .get("size_slug")
+                .and_then(|s| s.as_str())
+                .map(|s| s.to_string()),
+            hourly_price: droplet
+                .pointer("/size/price_hourly")
+                .and_then(|p| p.as_f64()),
+            state: ServerState::Running,
+            idle_action: None,
+            ready_marker: true,
+        })
+    }
+
+    /// New Droplets get their IP after a few seconds
+    fn wait_for_ip(&self, id: &str) -> Result<ServerHandle> {
+        let start = Instant::now();
+        loop {
+            let droplet = self.droplet(id)?;
+            match self.handle(&droplet) {
+                Ok(h) => return Ok(h),
+                Err(e) if start.elapsed() >= IP_TIMEOUT => return Err(e),
+                Err(_) => std::thread::sleep(Duration::from_secs(3)),
+            }
+        }
+    }
+
+    /// Runs a droplet action (e.g. `shutdown`) and waits until it finished
+    fn action(&self, handle: &ServerHandle, action: &str) -> Result<()> {
+        let api = self.api()?;
+        let v = api
+            .post(
+                &format!("/droplets/{}/actions", handle.id),
+                &serde_json::json!({ "type": action }),
+            )
+            .with_context(|| format!("digitalocean {} failed", action))?;
+        let id = v
+            .pointer("/action/id")
+            .and_then(|x| x.as_i64())
+            .ok_or_else(|| anyhow!("missing action id"))?;
+        let mut action = v["action"].clone();
+        loop {
+            match action.get("status").and_then(|s| s.as_str()) {
+                Some("completed") => return Ok(()),
+                Some("errored") => return Err(anyhow!("digitalocean action {} failed", id)),
+                _ => {}
+            }
+            std::thread::sleep(Duration::from_secs(3));
+            action = api
+                .get(&format!("/actions/{}", id))?
+                .get("action")
+                .cloned()
+                .ok_or_else(|| anyhow!("missing action"))?;
+        }
+    }
+
+    pub fn get_regions(token: String) -> Result<Vec<Region>> {
+        Self::client(Self::api_url(None), &token)?.list("/regions", "regions", &[])
+    }
+
+    pub fn get_sizes(token: String) -> Result<Vec<Size>> {
+        Self::client(Self::api_url(None), &token)?.list("/sizes", "sizes", &[])
+    }
+
+    pub fn get_ssh_keys(token: String) -> Result<Vec<SSHKey>> {
+        Self::client(Self::api_url(None), &token)?.list("/account/keys", "ssh_keys", &[])
+    }
+}
+
+impl Provider for DigitalOceanProvider {
+    fn rent(&self, project_key: &str, opts: &RentOptions) -> Result<ServerHandle> {
+        let timestampt = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let name = format!("cargo-remote-{}-{}", project_key, timestampt);
+
+        let body = serde_json::json!({
+            "name": name,
+            "region": self.cfg.region,
+            "size": self.cfg.size,
+            "image": self.cfg.image,
+            "ssh_keys": [self.cfg.ssh_key.clone()],
+            "user_data": self.cloud_init(opts),
+            "tags": self.tags(project_key),
+        });
+
+        let v = self
+            .api()?
+            .post("/droplets", &body)
+            .context("digitalocean create failed")?;
+        let id = v
+            .pointer("/droplet/id")
+            .and_then(|x| x.as_i64())
+            .ok_or_else(|| anyhow!("missing droplet id"))?
+            .to_string();
+
+        self.wait_for_ip(&id).or_else(|e| {
+            // Don't leave a Droplet behind, that we can't reach
+            let _ = self
+                .api()
+                .and_then(|api| api.delete(&format!("/droplets/{}", id)));
+            Err(e).context("droplet didn't get a public IP")
+        })
+    }
+
+    fn delete(&self, handle: &ServerHandle) -> Result<()> {
+        self.api()?
+            .delete(&format!("/droplets/{}", handle.id))
+            .context("digitalocean delete failed")
+    }
+
+    fn exists(&self, handle: &ServerHandle) -> Existence {
+        match self.droplet(&handle.id) {
+            Ok(_) => Existence::Exists,
+            Err(e) if http::is_not_found(&e) => Existence::Gone,
+            Err(e) => Existence::Unknown(format!("{:#}", e)),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<ServerHandle>> {
+        let droplets: Vec<Value> = self
+            .api()?
+            .list("/droplets", "droplets", &[("tag_name", TAG)])
+            .context("digitalocean list failed")?;
+        Ok(droplets
+            .iter()
+            .filter_map(|d| listed(&d["id"], self.handle(d)))
+            .collect())
+    }
+
+    fn find(&self, id_or_name: &str) -> Result<ServerHandle> {
+        if id_or_name.chars().all(|c| c.is_ascii_digit()) {
+            let droplet = self
+                .droplet(id_or_name)
+                .context("digitalocean find failed")?;
+            return self.handle(&droplet);
+        }
+        let droplets: Vec<Value> = self
+            .api()?
+            .list("/droplets", "droplets", &[("name", id_or_name)])
+            .context("digitalocean find failed")?;
+        let droplet = droplets
+            .first()
+            .ok_or_else(|| anyhow!("no droplet named `{}`", id_or_name))?;
+        self.handle(droplet)
+    }
+
+    fn status(&self, handle: &ServerHandle) -> Result<String> {
+        let droplet = self
+            .droplet(&handle.id)
+            .context("digitalocean status failed")?;
+        match droplet.get("status").and_then(|s| s.as_str()) {
+            Some("active") => Ok("running".into()),
+            Some(s) => Ok(s.to_string()),
+            None => Err(anyhow!("missing status")),
+        }
+    }
+
+    fn power_off(&self, handle: &ServerHandle) -> Result<()> {
+        self.action(handle, "shutdown")?;
+        let start = Instant::now();
+        while self.status(handle)? != "off" {
+            if start.elapsed() >= SHUTDOWN_TIMEOUT {
+                warn!("droplet {} didn't shut down, powering it off", handle.id);
+                return self.action(handle, "power_off");
+            }
+            std::thread::sleep(Duration::from_secs(3));
+        }
+        Ok(())
+    }
+
+    fn power_on(&self, handle: &ServerHandle) -> Result<()> {
+        self.action(handle, "power_on")
+    }
+
+    fn hourly_price(&self) -> Result<Option<f64>> {
+        let sizes: Vec<Value> = self
+            .api()?
+            .list("/sizes", "sizes", &[])
+            .context("digitalocean size lookup failed")?;
+        Ok(sizes
+            .iter()
+            .find(|s| s.get("slug").and_then(|x| x.as_str()) == Some(&self.cfg.size))
+            .and_then(|s| s.get("price_hourly"))
+            .and_then(|p| p.as_f64()))
+    }
+}
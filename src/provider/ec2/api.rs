@@ -0,0 +1,155 @@
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use reqwest::blocking::Client;
+use reqwest::{StatusCode, Url};
+use roxmltree::{Document, Node};
+use time::OffsetDateTime;
+
+use crate::provider::ec2::credentials::Credentials;
+use crate::provider::ec2::sigv4::{sign, uri_encode};
+
+const VERSION: &str = "2016-11-15";
+const CONTENT_TYPE: &str = "application/x-www-form-urlencoded; charset=utf-8";
+/// Retries when throttled (`RequestLimitExceeded`) or unavailable
+const MAX_RETRIES: u32 = 5;
+
+/// An error returned by the EC2 API (`<Response><Errors><Error><Code>...`)
+#[derive(Debug)]
+pub struct Ec2Error {
+    pub status: u16,
+    pub code: String,
+    pub message: String,
+}
+
+impl fmt::Display for Ec2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ec2 API error {} ({}): {}",
+            self.status, self.code, self.message
+        )
+    }
+}
+
+impl std::error::Error for Ec2Error {}
+
+impl Ec2Error {
+    fn from_response(status: u16, body: &str) -> Self {
+        let doc = Document::parse(body).ok();
+        let find = |name: &str| {
+            doc.as_ref().and_then(|d| {
+                d.descendants()
+                    .find(|n| n.tag_name().name() == name)
+                    .and_then(|n| n.text())
+                    .map(|t| t.to_string())
+            })
+        };
+        Ec2Error {
+            status,
+            code: find("Code").unwrap_or_else(|| "Unknown".into()),
+            message: find("Message").unwrap_or_else(|| body.trim().to_string()),
+        }
+    }
+}
+
+/// Client for the EC2 query API, signing every request with SigV4
+pub struct Api {
+    client: Client,
+    endpoint: Url,
+    region: String,
+    creds: Credentials,
+}
+
+impl Api {
+    pub fn new(endpoint: &str, region: &str, creds: Credentials) -> Result<Self> {
+        Ok(Self {
+            client: Client::builder().build()?,
+            endpoint: Url::parse(endpoint)?,
+            region: region.to_string(),
+            creds,
+        })
+    }
+
+    /// Runs `action` and returns the XML response
+    pub fn call(&self, action: &str, params: &[(String, String)]) -> Result<String> {
+        let mut body = format!("Action={}&Version={}", action, VERSION);
+        for (k, v) in params {
+            body.push_str(&format!("&{}={}", uri_encode(k), uri_encode(v)));
+        }
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{}", self.endpoint.host_str().unwrap_or_default(), port),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+
+        let mut attempt = 0;
+        loop {
+            let headers = sign(
+                &self.creds,
+                &self.region,
+                "ec2",
+                &host,
+                CONTENT_TYPE,
+                &body,
+                OffsetDateTime::now_utc(),
+            );
+            let mut req = self
+                .client
+                .post(self.endpoint.clone())
+                .header("content-type", CONTENT_TYPE)
+                .body(body.clone());
+            for (k, v) in headers {
+                req = req.header(k, v);
+            }
+            let resp = req.send()?;
+            let status = resp.status();
+            let text = resp.text()?;
+            if status.is_success() {
+                return Ok(text);
+            }
+            let err = Ec2Error::from_response(status.as_u16(), &text);
+            let retry =
+                status == StatusCode::SERVICE_UNAVAILABLE || err.code == "RequestLimitExceeded";
+            if !retry || attempt >= MAX_RETRIES {
+                return Err(err.into());
+            }
+            let wait = Duration::from_secs(1 << attempt);
+            warn!(
+                "ec2 {} returned {}, retrying in {}s",
+                action,
+                err.code,
+                wait.as_secs()
+            );
+            std::thread::sleep(wait);
+            attempt += 1;
+        }
+    }
+}
+
+pub fn parse(xml: &str) -> Result<Document<'_>> {
+    Document::parse(xml).map_err(|e| anyhow!("ec2: invalid response: {}", e))
+}
+
+/// First direct child named `name`
+pub fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.tag_name().name() == name)
+}
+
+pub fn text(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+}
+
+/// The `<item>`s of a list like `<instancesSet>`
+pub fn items<'a, 'i>(node: Node<'a, 'i>, set: &str) -> Vec<Node<'a, 'i>> {
+    child(node, set)
+        .map(|s| {
+            s.children()
+                .filter(|n| n.tag_name().name() == "item")
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,173 @@
+use std::{fmt, process::exit};
+
+use anyhow::Result;
+use inquire::{Confirm, CustomType, Select, Text};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{
+        mode::{ConfigData, Mode},
+        SavedConfig,
+    },
+    provider::{
+        ec2::{
+            credentials::{default_region, profile_name, Credentials},
+            Ec2Provider,
+        },
+        CloudConfig, Guards,
+    },
+};
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ec2Config {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>, // AWS profile, otherwise env / AWS_PROFILE / `default`
+    pub region: String,
+    pub instance_type: String,
+    pub image: String,    // AMI id, e.g. `ami-0faab6bdbac9486fb`
+    pub key_name: String, // EC2 key pair name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub security_group: Option<String>, // id, has to allow ssh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subnet: Option<String>, // id, default subnet of the VPC without
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_size: Option<u32>, // GB of the root volume, the AMI's size without
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub spot: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spot_max_price: Option<String>, // USD per hour, on-demand price without
+    pub username: Option<String>,
+    pub private_key: String, // local private key path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>, // defaults to the regional EC2 endpoint
+    #[serde(flatten)]
+    pub guards: Guards,
+}
+
+impl CloudConfig for Ec2Config {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn private_key_path(&self) -> String {
+        self.private_key.clone()
+    }
+
+    fn guards(&self) -> Guards {
+        self.guards.clone()
+    }
+}
+
+impl fmt::Display for Ec2Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Ec2Config(region: {}, instance_type: {}{})",
+            self.region,
+            self.instance_type,
+            if self.spot { ", spot" } else { "" }
+        )
+    }
+}
+
+pub fn ec2_config_wizzard(name: String) -> Result<SavedConfig> {
+    let profile =
+        Text::new("AWS profile (credentials from the environment or ~/.aws/credentials):")
+            .with_initial_value(&profile_name(None))
+            .prompt()?;
+    // Without a profile the AWS_* environment variables are used
+    let profile = Some(profile.trim().to_string()).filter(|p| !p.is_empty() && p != "default");
+    if let Err(e) = Credentials::load(profile.as_deref()) {
+        eprintln!("***** {:#}. This may cause Problems later! *****", e);
+    }
+
+    let initial_region = default_region(profile.as_deref()).unwrap_or("eu-central-1".into());
+    let region = match Ec2Provider::get_regions(profile.as_deref(), &initial_region) {
+        Ok(regions) => {
+            let start = regions
+                .iter()
+                .position(|r| *r == initial_region)
+                .unwrap_or(0);
+            Select::new("Region: ", regions)
+                .with_starting_cursor(start)
+                .prompt()?
+        }
+        Err(e) => {
+            eprintln!("***** We could not query the Regions ({:#}). Your credentials could be wrong or your Computer may not be connected to the Internet. This may cause Problems later! *****", e);
+            Text::new("Region: ")
+                .with_initial_value(&initial_region)
+                .prompt()?
+        }
+    };
+
+    let instance_type = Text::new("Instance type: ")
+        .with_initial_value("c7i.4xlarge")
+        .prompt()?;
+
+    let ubuntu = Ec2Provider::latest_ubuntu(profile.as_deref(), &region).unwrap_or_default();
+    let image = Text::new("AMI id (This tool will use apt to install rust and other dependencies! Ubuntu recommendet):")
+        .with_initial_value(&ubuntu)
+        .prompt()?;
+
+    let key_name = if let Ok(keys) = Ec2Provider::get_key_pairs(profile.as_deref(), &region) {
+        if keys.is_empty() {
+            eprint!("You need to import your SSH-Public Key as EC2 key pair in order to connect to the instance later on. Exitiing...");
+            exit(1);
+        }
+        Select::new("Select your key pair: ", keys).prompt()?
+    } else {
+        Text::new("EC2 key pair name:").prompt()?
+    };
+
+    let security_group = match Ec2Provider::get_security_groups(profile.as_deref(), &region) {
+        Ok(groups) if !groups.is_empty() => Select::new(
+            "Security group (has to allow ssh from this machine): ",
+            groups,
+        )
+        .prompt_skippable()?
+        .map(|g| g.id),
+        _ => Text::new(
+            "Security group id (has to allow ssh from this machine, empty for the default):",
+        )
+        .prompt_skippable()?
+        .filter(|g| !g.trim().is_empty()),
+    };
+
+    let disk_size = CustomType::<u32>::new("Root volume size in GB (Esc for the image's size):")
+        .with_default(50)
+        .prompt_skippable()?;
+
+    let spot = Confirm::new(
+        "Use spot instances? (much cheaper, but may be interrupted and can't be paused)",
+    )
+    .with_default(false)
+    .prompt()?;
+
+    let private_key = Text::new("Local SSH Private Key Path: ").prompt()?;
+
+    Ok(SavedConfig {
+        mode: Mode::Ec2,
+        data: ConfigData::Ec2(Ec2Config {
+            name,
+            profile,
+            region,
+            instance_type,
+            image,
+            key_name,
+            security_group,
+            subnet: None,
+            disk_size,
+            spot,
+            spot_max_price: None,
+            username: Some("root".to_string()),
+            private_key,
+            api_url: None,
+            guards: Guards::default(),
+        }),
+    })
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+/// AWS access key, as used by the AWS CLI and SDKs
+#[derive(Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Credentials({})", self.access_key_id)
+    }
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn aws_file(env_name: &str, default: &str) -> Option<PathBuf> {
+    env(env_name)
+        .map(PathBuf::from)
+        .or_else(|| env("HOME").map(|home| PathBuf::from(home).join(".aws").join(default)))
+}
+
+/// Sections of an AWS ini file (`~/.aws/credentials`, `~/.aws/config`)
+fn read_ini(path: Option<PathBuf>) -> HashMap<String, HashMap<String, String>> {
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let Some(content) = path.and_then(|p| std::fs::read_to_string(p).ok()) else {
+        return sections;
+    };
+    let mut current = None;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name.trim().to_string());
+            sections.entry(name.trim().to_string()).or_default();
+        } else if let (Some(section), Some((k, v))) = (&current, line.split_once('=')) {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(k.trim().to_string(), v.trim().to_string());
+        }
+    }
+    sections
+}
+
+/// The profile to use: the configured one, then `AWS_PROFILE`, then `default`
+pub fn profile_name(configured: Option<&str>) -> String {
+    configured
+        .map(|p| p.to_string())
+        .or_else(|| env("AWS_PROFILE"))
+        .unwrap_or_else(|| "default".into())
+}
+
+/// Settings of a profile: `~/.aws/credentials` first, then `~/.aws/config`
+fn profile(name: &str) -> HashMap<String, String> {
+    let mut settings = HashMap::new();
+    let config_section = if name == "default" {
+        name.to_string()
+    } else {
+        format!("profile {}", name)
+    };
+    if let Some(s) = read_ini(aws_file("AWS_CONFIG_FILE", "config")).remove(config_section.as_str())
+    {
+        settings.extend(s);
+    }
+    if let Some(s) = read_ini(aws_file("AWS_SHARED_CREDENTIALS_FILE", "credentials")).remove(name) {
+        settings.extend(s);
+    }
+    settings
+}
+
+impl Credentials {
+    /// Like the AWS CLI: `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` / `AWS_SESSION_TOKEN`,
+    /// otherwise the profile from the shared credentials and config files. A profile set in the
+    /// cargo-remote config always wins over the environment
+    pub fn load(configured_profile: Option<&str>) -> Result<Self> {
+        if configured_profile.is_none() {
+            if let (Some(access_key_id), Some(secret_access_key)) =
+                (env("AWS_ACCESS_KEY_ID"), env("AWS_SECRET_ACCESS_KEY"))
+            {
+                return Ok(Credentials {
+                    access_key_id,
+                    secret_access_key,
+                    session_token: env("AWS_SESSION_TOKEN"),
+                });
+            }
+        }
+
+        let name = profile_name(configured_profile);
+        let mut p = profile(&name);
+        match (
+            p.remove("aws_access_key_id"),
+            p.remove("aws_secret_access_key"),
+        ) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: p.remove("aws_session_token"),
+            }),
+            _ => Err(anyhow!(
+                "no AWS credentials: set AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY or add profile `{}` to ~/.aws/credentials",
+                name
+            )),
+        }
+    }
+}
+
+/// Region of a profile in the AWS files, `AWS_REGION` / `AWS_DEFAULT_REGION` before that
+pub fn default_region(configured_profile: Option<&str>) -> Option<String> {
+    env("AWS_REGION")
+        .or_else(|| env("AWS_DEFAULT_REGION"))
+        .or_else(|| profile(&profile_name(configured_profile)).remove("region"))
+}
@@ -0,0 +1,492 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::provider::cloud_init::{self, Recipe};
+use crate::provider::handle::ServerState;
+use crate::provider::http;
+use crate::provider::{
+    creator, ec2::config::Ec2Config, Existence, IdleAction, Provider, ProviderKind, RentOptions,
+    ServerHandle,
+};
+use anyhow::{anyhow, Context, Result};
+use api::{items, parse, text, Api, Ec2Error};
+use base64::Engine;
+use credentials::Credentials;
+use log::warn;
+use roxmltree::Node;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+pub mod api;
+pub mod config;
+pub mod credentials;
+pub mod sigv4;
+
+const API_URL_ENV: &str = "CARGO_REMOTE_EC2_API_URL";
+const TAG_TOOL: &str = "tool";
+/// How long a new instance may take to be running with a public IP
+const RUNNING_TIMEOUT: Duration = Duration::from_secs(300);
+const STATE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Canonical, the publisher of the Ubuntu AMIs
+const UBUNTU_OWNER: &str = "099720109477";
+
+fn param(k: impl Into<String>, v: impl ToString) -> (String, String) {
+    (k.into(), v.to_string())
+}
+
+/// An instance of a `DescribeInstances` / `RunInstances` response
+struct Instance {
+    id: String,
+    state: String,
+    public_ip: Option<String>,
+    launch_time: Option<String>,
+    instance_type: Option<String>,
+    /// The `config` tag
+    config: Option<String>,
+}
+
+impl Instance {
+    fn from_xml(item: Node) -> Option<Self> {
+        Some(Instance {
+            id: text(item, "instanceId")?,
+            state: api::child(item, "instanceState")
+                .and_then(|s| text(s, "name"))
+                .unwrap_or_default(),
+            public_ip: text(item, "ipAddress"),
+            launch_time: text(item, "launchTime"),
+            instance_type: text(item, "instanceType"),
+            config: items(item, "tagSet")
+                .into_iter()
+                .find(|t| text(*t, "key").as_deref() == Some("config"))
+                .and_then(|t| text(t, "value")),
+        })
+    }
+
+    fn gone(&self) -> bool {
+        matches!(self.state.as_str(), "shutting-down" | "terminated")
+    }
+}
+
+pub struct Ec2Provider {
+    pub cfg: Ec2Config,
+}
+
+#[derive(Debug)]
+pub struct SecurityGroup {
+    pub id: String,
+    name: String,
+    description: String,
+}
+
+impl std::fmt::Display for SecurityGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}: {})", self.id, self.name, self.description)
+    }
+}
+
+impl Ec2Provider {
+    pub fn api_url(configured: Option<&str>, region: &str) -> String {
+        let regional = format!("https://ec2.{}.amazonaws.com", region);
+        http::base_url(API_URL_ENV, configured, &regional)
+    }
+
+    fn api(&self) -> Result<Api> {
+        Self::client(
+            self.cfg.profile.as_deref(),
+            &self.cfg.region,
+            self.cfg.api_url.as_deref(),
+        )
+    }
+
+    fn client(profile: Option<&str>, region: &str, api_url: Option<&str>) -> Result<Api> {
+        Api::new(
+            &Self::api_url(api_url, region),
+            region,
+            Credentials::load(profile)?,
+        )
+    }
+
+    fn cloud_init(&self, opts: &RentOptions) -> String {
+        // Ubuntu AMIs only allow `ubuntu` to log in, cargo-remote works as root. The instance
+        // deletes itself by shutting down (`InstanceInitiatedShutdownBehavior=terminate`)
+        let user_data = cloud_init::user_data(
+            opts,
+            &Recipe {
+                baked: false,
+                prelude: String::new(),
+            },
+        );
+        user_data.replacen("#cloud-config\n", "#cloud-config\ndisable_root: false\n", 1)
+    }
+
+    /// Tags of launched instances, `Name` is what the console shows
+    fn tags(&self, name: &str, project_key: &str) -> Vec<(&'static str, String)> {
+        let (user, host) = creator();
+        vec![
+            ("Name", name.to_string()),
+            (TAG_TOOL, "cargo-remote".to_string()),
+            ("project", project_key.to_string()),
+            ("config", self.cfg.name.clone()),
+            ("created-by", user),
+            ("created-on", host),
+        ]
+    }
+
+    fn handle(&self, instance: &Instance) -> ServerHandle {
+        ServerHandle {
+            provider: ProviderKind::Ec2,
+            // Configs may share the account, the tag names the one that launched it
+            config: instance
+                .config
+                .clone()
+                .unwrap_or_else(|| self.cfg.name.clone()),
+            id: instance.id.clone(),
+            // Stopped instances have no public IP, they get a new one when started again
+            host: instance.public_ip.clone().unwrap_or_default(),
+            port: 22,
+            username: self.cfg.username.clone().unwrap_or("root".into()),
+            created_at: instance
+                .launch_time
+                .as_deref()
+                .and_then(|t| OffsetDateTime::parse(t, &Rfc3339).ok())
+                .map(|t| t.unix_timestamp().max(0) as u64),
+            server_type: instance.instance_type.clone(),
+            hourly_price: None,
+            state: ServerState::Running,
+            idle_action: None,
+            ready_marker: true,
+        }
+    }
+
+    /// Instances of a `DescribeInstances` call, following `nextToken`
+    fn describe(&self, params: &[(String, String)]) -> Result<Vec<Instance>> {
+        let api = self.api()?;
+        let mut instances = Vec::new();
+        let mut next_token: Option<String> = None;
+        loop {
+            let mut params = params.to_vec();
+            if let Some(token) = next_token.take() {
+                params.push(param("NextToken", token));
+            }
+            let xml = api.call("DescribeInstances", &params)?;
+            let doc = parse(&xml)?;
+            let root = doc.root_element();
+            for reservation in items(root, "reservationSet") {
+                instances.extend(
+                    items(reservation, "instancesSet")
+                        .into_iter()
+                        .filter_map(Instance::from_xml),
+                );
+            }
+            next_token = text(root, "nextToken").filter(|t| !t.is_empty());
+            if next_token.is_none() {
+                return Ok(instances);
+            }
+        }
+    }
+
+    fn instance(&self, id: &str) -> Result<Instance> {
+        self.describe(&[param("InstanceId.1", id)])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("instance {} not found", id))
+    }
+
+    /// Waits until the instance is in `state`
+    fn wait_state(&self, id: &str, state: &str, timeout: Duration) -> Result<Instance> {
+        let start = Instant::now();
+        loop {
+            let instance = self.instance(id)?;
+            if instance.state == state {
+                return Ok(instance);
+            }
+            if instance.gone() {
+                return Err(anyhow!("instance {} is {}", id, instance.state));
+            }
+            if start.elapsed() >= timeout {
+                return Err(anyhow!(
+                    "instance {} still `{}` after {}s",
+                    id,
+                    instance.state,
+                    timeout.as_secs()
+                ));
+            }
+            std::thread::sleep(Duration::from_secs(3));
+        }
+    }
+
+    /// Root device of the AMI, to resize it with `disk_size`
+    fn root_device(&self) -> Result<String> {
+        let xml = self
+            .api()?
+            .call("DescribeImages", &[param("ImageId.1", &self.cfg.image)])?;
+        let doc = parse(&xml)?;
+        items(doc.root_element(), "imagesSet")
+            .into_iter()
+            .find_map(|i| text(i, "rootDeviceName"))
+            .ok_or_else(|| anyhow!("image {} not found", self.cfg.image))
+    }
+
+    fn run_params(&self, project_key: &str, opts: &RentOptions) -> Result<Vec<(String, String)>> {
+        let timestampt = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let name = format!("cargo-remote-{}-{}", project_key, timestampt);
+        let user_data = base64::engine::general_purpose::STANDARD.encode(self.cloud_init(opts));
+
+        let self_delete = opts
+            .idle
+            .as_ref()
+            .is_some_and(|i| i.action == IdleAction::PoweroffThenReap);
+        let mut params = vec![
+            param("ImageId", &self.cfg.image),
+            param("InstanceType", &self.cfg.instance_type),
+            param("MinCount", 1),
+            param("MaxCount", 1),
+            param("KeyName", &self.cfg.key_name),
+            param("UserData", user_data),
+            // Makes retried requests idempotent
+            param("ClientToken", &name),
+            param(
+                "InstanceInitiatedShutdownBehavior",
+                if self.cfg.spot || self_delete {
+                    "terminate"
+                } else {
+                    "stop"
+                },
+            ),
+            param("TagSpecification.1.ResourceType", "instance"),
+        ];
+        for (i, (k, v)) in self.tags(&name, project_key).into_iter().enumerate() {
+            params.push(param(format!("TagSpecification.1.Tag.{}.Key", i + 1), k));
+            params.push(param(format!("TagSpecification.1.Tag.{}.Value", i + 1), v));
+        }
+        if let Some(sg) = &self.cfg.security_group {
+            params.push(param("SecurityGroupId.1", sg));
+        }
+        if let Some(subnet) = &self.cfg.subnet {
+            params.push(param("SubnetId", subnet));
+        }
+        if let Some(size) = self.cfg.disk_size {
+            params.push(param(
+                "BlockDeviceMapping.1.DeviceName",
+                self.root_device()?,
+            ));
+            params.push(param("BlockDeviceMapping.1.Ebs.VolumeSize", size));
+            params.push(param("BlockDeviceMapping.1.Ebs.VolumeType", "gp3"));
+            params.push(param("BlockDeviceMapping.1.Ebs.DeleteOnTermination", true));
+        }
+        if self.cfg.spot {
+            params.push(param("InstanceMarketOptions.MarketType", "spot"));
+            params.push(param(
+                "InstanceMarketOptions.SpotOptions.SpotInstanceType",
+                "one-time",
+            ));
+            params.push(param(
+                "InstanceMarketOptions.SpotOptions.InstanceInterruptionBehavior",
+                "terminate",
+            ));
+            if let Some(max) = &self.cfg.spot_max_price {
+                params.push(param("InstanceMarketOptions.SpotOptions.MaxPrice", max));
+            }
+        }
+        Ok(params)
+    }
+
+    pub fn get_regions(profile: Option<&str>, region: &str) -> Result<Vec<String>> {
+        let xml = Self::client(profile, region, None)?.call("DescribeRegions", &[])?;
+        let doc = parse(&xml)?;
+        let mut regions: Vec<String> = items(doc.root_element(), "regionInfo")
+            .into_iter()
+            .filter_map(|r| text(r, "regionName"))
+            .collect();
+        regions.sort();
+        Ok(regions)
+    }
+
+    pub fn get_key_pairs(profile: Option<&str>, region: &str) -> Result<Vec<String>> {
+        let xml = Self::client(profile, region, None)?.call("DescribeKeyPairs", &[])?;
+        let doc = parse(&xml)?;
+        Ok(items(doc.root_element(), "keySet")
+            .into_iter()
+            .filter_map(|k| text(k, "keyName"))
+            .collect())
+    }
+
+    pub fn get_security_groups(profile: Option<&str>, region: &str) -> Result<Vec<SecurityGroup>> {
+        let xml = Self::client(profile, region, None)?.call("DescribeSecurityGroups", &[])?;
+        let doc = parse(&xml)?;
+        Ok(items(doc.root_element(), "securityGroupInfo")
+            .into_iter()
+            .filter_map(|g| {
+                Some(SecurityGroup {
+                    id: text(g, "groupId")?,
+                    name: text(g, "groupName").unwrap_or_default(),
+                    description: text(g, "groupDescription").unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    /// The newest Ubuntu 22.04 AMI of Canonical in the region
+    pub fn latest_ubuntu(profile: Option<&str>, region: &str) -> Result<String> {
+        let xml = Self::client(profile, region, None)?.call(
+            "DescribeImages",
+            &[
+                param("Owner.1", UBUNTU_OWNER),
+                param("Filter.1.Name", "name"),
+                param(
+                    "Filter.1.Value.1",
+                    "ubuntu/images/hvm-ssd/ubuntu-jammy-22.04-amd64-server-*",
+                ),
+                param("Filter.2.Name", "state"),
+                param("Filter.2.Value.1", "available"),
+            ],
+        )?;
+        let doc = parse(&xml)?;
+        items(doc.root_element(), "imagesSet")
+            .into_iter()
+            .filter_map(|i| Some((text(i, "creationDate")?, text(i, "imageId")?)))
+            .max()
+            .map(|(_, id)| id)
+            .ok_or_else(|| anyhow!("no Ubuntu 22.04 image in {}", region))
+    }
+}
+
+impl Provider for Ec2Provider {
+    fn rent(&self, project_key: &str, opts: &RentOptions) -> Result<ServerHandle> {
+        let xml = self
+            .api()?
+            .call("RunInstances", &self.run_params(project_key, opts)?)
+            .context("ec2 RunInstances failed")?;
+        let doc = parse(&xml)?;
+        let id = items(doc.root_element(), "instancesSet")
+            .into_iter()
+            .find_map(|i| text(i, "instanceId"))
+            .ok_or_else(|| anyhow!("missing instance id"))?;
+
+        let start = Instant::now();
+        loop {
+            let instance = match self.wait_state(&id, "running", RUNNING_TIMEOUT) {
+                Ok(i) => i,
+                Err(e) => {
+                    // Don't leave an instance behind, that we can't reach
+                    let _ = self.api().and_then(|api| {
+                        api.call("TerminateInstances", &[param("InstanceId.1", &id)])
+                    });
+                    return Err(e).context("instance didn't start");
+                }
+            };
+            if instance.public_ip.is_some() {
+                return Ok(self.handle(&instance));
+            }
+            if start.elapsed() >= RUNNING_TIMEOUT {
+                let _ = self
+                    .api()
+                    .and_then(|api| api.call("TerminateInstances", &[param("InstanceId.1", &id)]));
+                return Err(anyhow!(
+                    "instance {} has no public IP; use a subnet that assigns one",
+                    id
+                ));
+            }
+            std::thread::sleep(Duration::from_secs(3));
+        }
+    }
+
+    fn delete(&self, handle: &ServerHandle) -> Result<()> {
+        self.api()?
+            .call("TerminateInstances", &[param("InstanceId.1", &handle.id)])
+            .context("ec2 TerminateInstances failed")?;
+        Ok(())
+    }
+
+    fn exists(&self, handle: &ServerHandle) -> Existence {
+        match self.describe(&[param("InstanceId.1", &handle.id)]) {
+            Ok(instances) => match instances.first() {
+                Some(i) if !i.gone() => Existence::Exists,
+                // Terminated instances are still listed for about an hour
+                Some(_) => Existence::Gone,
+                None => Existence::Gone,
+            },
+            Err(e) => match e.downcast_ref::<Ec2Error>() {
+                Some(ee) if ee.code == "InvalidInstanceID.NotFound" => Existence::Gone,
+                _ => Existence::Unknown(format!("{:#}", e)),
+            },
+        }
+    }
+
+    fn list(&self) -> Result<Vec<ServerHandle>> {
+        let instances = self
+            .describe(&[
+                param("Filter.1.Name", format!("tag:{}", TAG_TOOL)),
+                param("Filter.1.Value.1", "cargo-remote"),
+                param("Filter.2.Name", "instance-state-name"),
+                param("Filter.2.Value.1", "pending"),
+                param("Filter.2.Value.2", "running"),
+                param("Filter.2.Value.3", "stopping"),
+                param("Filter.2.Value.4", "stopped"),
+            ])
+            .context("ec2 list failed")?;
+        Ok(instances.iter().map(|i| self.handle(i)).collect())
+    }
+
+    fn find(&self, id_or_name: &str) -> Result<ServerHandle> {
+        let instance = if id_or_name.starts_with("i-") {
+            self.instance(id_or_name)
+        } else {
+            self.describe(&[
+                param("Filter.1.Name", "tag:Name"),
+                param("Filter.1.Value.1", id_or_name),
+            ])?
+            .into_iter()
+            .find(|i| !i.gone())
+            .ok_or_else(|| anyhow!("no instance named `{}`", id_or_name))
+        }
+        .context("ec2 find failed")?;
+        if instance.gone() {
+            return Err(anyhow!("instance {} is {}", instance.id, instance.state));
+        }
+        Ok(self.handle(&instance))
+    }
+
+    fn status(&self, handle: &ServerHandle) -> Result<String> {
+        let instance = self.instance(&handle.id).context("ec2 status failed")?;
+        match instance.state.as_str() {
+            "stopped" => Ok("off".into()),
+            s => Ok(s.to_string()),
+        }
+    }
+
+    fn power_off(&self, handle: &ServerHandle) -> Result<()> {
+        if self.cfg.spot {
+            return Err(anyhow!(
+                "spot instances can't be stopped; end it with `cargo remote end`"
+            ));
+        }
+        self.api()?
+            .call("StopInstances", &[param("InstanceId.1", &handle.id)])
+            .context("ec2 StopInstances failed")?;
+        self.wait_state(&handle.id, "stopped", STATE_TIMEOUT)?;
+        Ok(())
+    }
+
+    fn power_on(&self, handle: &ServerHandle) -> Result<()> {
+        self.api()?
+            .call("StartInstances", &[param("InstanceId.1", &handle.id)])
+            .context("ec2 StartInstances failed")?;
+        let instance = self.wait_state(&handle.id, "running", STATE_TIMEOUT)?;
+        if instance.public_ip.as_deref() != Some(handle.host.as_str()) {
+            warn!(
+                "instance {} got a new public IP: {}",
+                handle.id,
+                instance.public_ip.as_deref().unwrap_or("none")
+            );
+        }
+        Ok(())
+    }
+
+    /// Prices aren't available through the EC2 API
+    fn hourly_price(&self) -> Result<Option<f64>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+use anyhow::Result;
+use inquire::Text;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{
+    config::{
+        mode::{ConfigData, Mode},
+        SavedConfig,
+    },
+    provider::{CloudConfig, Guards},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecConfig {
+    pub name: String,
+    pub executable: String, // plugin speaking the protocol of ADD_PROVIDER.md
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    pub private_key: String, // local private key path
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub settings: Map<String, Value>, // passed to the plugin as they are
+    #[serde(flatten)]
+    pub guards: Guards,
+}
+
+impl CloudConfig for ExecConfig {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn private_key_path(&self) -> String {
+        self.private_key.clone()
+    }
+
+    fn guards(&self) -> Guards {
+        self.guards.clone()
+    }
+}
+
+impl fmt::Display for ExecConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ExecConfig(executable: {})", self.executable)
+    }
+}
+
+pub fn exec_config_wizzard(name: String) -> Result<SavedConfig> {
+    println!("The plugin is called with a JSON request on stdin for every operation, see ADD_PROVIDER.md");
+    let executable = Text::new("Plugin executable (absolute path or in PATH): ").prompt()?;
+    let args = Text::new("Additional arguments (space separated): ")
+        .prompt_skippable()?
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|a| a.to_string())
+        .collect();
+    let private_key = Text::new("Local SSH Private Key Path: ").prompt()?;
+
+    Ok(SavedConfig {
+        mode: Mode::Exec,
+        data: ConfigData::Exec(ExecConfig {
+            name,
+            executable,
+            args,
+            private_key,
+            settings: Map::new(),
+            guards: Guards::default(),
+        }),
+    })
+}
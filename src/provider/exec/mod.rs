@@ -0,0 +1,229 @@
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::provider::cloud_init::{self, Recipe};
+use crate::provider::handle::ServerState;
+use crate::provider::{
+    exec::config::ExecConfig, Existence, IdleAction, Provider, ProviderKind, RentOptions,
+    ServerHandle,
+};
+
+pub mod config;
+
+/// Version of the JSON protocol spoken with plugins. Bumped on incompatible changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A provider implemented by an external executable, see ADD_PROVIDER.md
+pub struct ExecProvider {
+    pub cfg: ExecConfig,
+}
+
+/// The plugin doesn't implement an (optional) operation
+#[derive(Debug)]
+pub struct Unsupported(pub String);
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the provider plugin doesn't support `{}`", self.0)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+#[derive(Deserialize)]
+struct Response {
+    version: u32,
+    ok: bool,
+    #[serde(default)]
+    result: Value,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    unsupported: bool,
+}
+
+/// A Server as returned by the plugin
+#[derive(Deserialize)]
+struct PluginServer {
+    id: String,
+    host: String,
+    #[serde(default = "default_port")]
+    port: u16,
+    username: Option<String>,
+    created_at: Option<u64>,
+    server_type: Option<String>,
+    hourly_price: Option<f64>,
+    /// The Server ran the `user_data` of `rent`, builds wait for the ready marker
+    #[serde(default)]
+    ready_marker: bool,
+}
+
+fn default_port() -> u16 {
+    22
+}
+
+impl ExecProvider {
+    /// Runs the plugin with one request. Its stderr is passed through for progress messages
+    fn call(&self, op: &str, mut params: Value) -> Result<Value> {
+        params["version"] = PROTOCOL_VERSION.into();
+        params["op"] = op.into();
+        params["config"] = serde_json::json!({
+            "name": self.cfg.name,
+            "settings": self.cfg.settings,
+        });
+
+        let mut child = Command::new(&self.cfg.executable)
+            .args(&self.cfg.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("could not run provider plugin `{}`", self.cfg.executable))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(serde_json::to_string(&params)?.as_bytes())?;
+        }
+        let out = child.wait_with_output()?;
+        if !out.status.success() {
+            return Err(anyhow!(
+                "provider plugin `{}` failed on `{}` ({})",
+                self.cfg.executable,
+                op,
+                out.status
+            ));
+        }
+
+        let resp: Response = serde_json::from_slice(&out.stdout)
+            .with_context(|| format!("invalid response of the provider plugin to `{}`", op))?;
+        if resp.version != PROTOCOL_VERSION {
+            return Err(anyhow!(
+                "provider plugin speaks protocol version {}, expected {}",
+                resp.version,
+                PROTOCOL_VERSION
+            ));
+        }
+        if resp.unsupported {
+            return Err(Unsupported(op.to_string()).into());
+        }
+        if !resp.ok {
+            return Err(anyhow!(
+                "provider plugin: {}",
+                resp.error.unwrap_or_else(|| format!("`{}` failed", op))
+            ));
+        }
+        Ok(resp.result)
+    }
+
+    fn server(handle: &ServerHandle) -> Value {
+        serde_json::json!({ "server": handle })
+    }
+
+    fn handle(&self, v: Value) -> Result<ServerHandle> {
+        let s: PluginServer =
+            serde_json::from_value(v).context("invalid server in the plugin response")?;
+        Ok(ServerHandle {
+            provider: ProviderKind::Exec,
+            config: self.cfg.name.clone(),
+            id: s.id,
+            host: s.host,
+            port: s.port,
+            username: s.username.unwrap_or("root".into()),
+            created_at: s.created_at,
+            server_type: s.server_type,
+            hourly_price: s.hourly_price,
+            state: ServerState::Running,
+            idle_action: None,
+            ready_marker: s.ready_marker,
+        })
+    }
+}
+
+impl Provider for ExecProvider {
+    fn rent(&self, project_key: &str, opts: &RentOptions) -> Result<ServerHandle> {
+        let idle = opts.idle.as_ref().map(|i| {
+            serde_json::json!({
+                "timeout_secs": i.timeout.as_secs(),
+                "action": match i.action {
+                    IdleAction::Poweroff => "poweroff",
+                    IdleAction::PoweroffThenReap => "poweroff-then-reap",
+                },
+            })
+        });
+        let user_data = cloud_init::user_data(
+            opts,
+            &Recipe {
+                baked: false,
+                prelude: String::new(),
+            },
+        );
+        let v = self.call(
+            "rent",
+            serde_json::json!({
+                "project_key": project_key,
+                "options": { "preinstall": opts.preinstall, "idle": idle, "user_data": user_data },
+            }),
+        )?;
+        self.handle(v)
+    }
+
+    fn delete(&self, handle: &ServerHandle) -> Result<()> {
+        self.call("delete", Self::server(handle))?;
+        Ok(())
+    }
+
+    fn exists(&self, handle: &ServerHandle) -> Existence {
+        match self
+            .call("exists", Self::server(handle))
+            .map(|v| v.as_bool())
+        {
+            Ok(Some(true)) => Existence::Exists,
+            Ok(Some(false)) => Existence::Gone,
+            Ok(None) => Existence::Unknown("`exists` didn't return a boolean".into()),
+            Err(e) => Existence::Unknown(format!("{:#}", e)),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<ServerHandle>> {
+        match self.call("list", serde_json::json!({}))? {
+            Value::Array(servers) => servers.into_iter().map(|s| self.handle(s)).collect(),
+            _ => Err(anyhow!("`list` didn't return an array")),
+        }
+    }
+
+    fn find(&self, id_or_name: &str) -> Result<ServerHandle> {
+        let v = self.call("find", serde_json::json!({ "id_or_name": id_or_name }))?;
+        self.handle(v)
+    }
+
+    fn status(&self, handle: &ServerHandle) -> Result<String> {
+        match self.call("status", Self::server(handle)) {
+            Ok(Value::String(s)) => Ok(s),
+            Ok(_) => Err(anyhow!("`status` didn't return a string")),
+            // Without it, a Server that exists is assumed to be running
+            Err(e) if e.downcast_ref::<Unsupported>().is_some() => Ok("running".into()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn power_off(&self, handle: &ServerHandle) -> Result<()> {
+        self.call("power_off", Self::server(handle))?;
+        Ok(())
+    }
+
+    fn power_on(&self, handle: &ServerHandle) -> Result<()> {
+        self.call("power_on", Self::server(handle))?;
+        Ok(())
+    }
+
+    fn hourly_price(&self) -> Result<Option<f64>> {
+        match self.call("hourly_price", serde_json::json!({})) {
+            Ok(v) => Ok(v.as_f64()),
+            Err(e) if e.downcast_ref::<Unsupported>().is_some() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
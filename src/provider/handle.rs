@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
 
-use crate::provider::ProviderKind;
+use crate::provider::{IdleAction, ProviderKind};
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct ServerHandle {
@@ -10,6 +11,64 @@ pub struct ServerHandle {
     pub host: String,
     pub port: u16,
     pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<u64>, // unix timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hourly_price: Option<f64>, // gross, in the currency of the account
+    #[serde(default, skip_serializing_if = "ServerState::is_running")]
+    pub state: ServerState,
+    /// What the idle watchdog was set up to do, see `begin --idle-action`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_action: Option<IdleAction>,
+    /// Whether the Server runs our cloud-init recipe, which creates the ready marker once it is
+    /// provisioned. False for Servers adopted without `--check-ready` and plugin Servers that
+    /// don't run it
+    #[serde(default = "ready_marker", skip_serializing_if = "is_true")]
+    pub ready_marker: bool,
+}
+
+fn ready_marker() -> bool {
+    true
+}
+
+fn is_true(b: &bool) -> bool {
+    *b
+}
+
+/// Whether a session is paused (`cargo remote pause`) or owned by a single command
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerState {
+    #[default]
+    Running,
+    /// Powered off, disk (and `target/`) kept
+    Paused,
+    /// Rented for `--ephemeral` or `image bake`, never selected for other sessions
+    Ephemeral,
+}
+
+impl ServerState {
+    fn is_running(&self) -> bool {
+        *self == ServerState::Running
+    }
+}
+
+impl ServerHandle {
+    pub fn uptime(&self) -> Option<Duration> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        Some(Duration::from_secs(now.saturating_sub(self.created_at?)))
+    }
+
+    /// Cost so far. Servers are billed per started hour
+    pub fn cost(&self) -> Option<f64> {
+        let hours = (self.uptime()?.as_secs() as f64 / 3600.0).ceil().max(1.0);
+        Some(hours * self.hourly_price?)
+    }
 }
 
 impl std::fmt::Display for ServerHandle {
@@ -1,27 +1,36 @@
 use std::{fmt, process::exit};
 
 use anyhow::Result;
-use inquire::{Password, Select, Text};
+use inquire::{CustomType, Select, Text};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     config::{
         mode::{ConfigData, Mode},
+        secret::{secret_wizzard, Secret},
         SavedConfig,
     },
-    provider::{hetzner::HetznerProvider, CloudConfig},
+    provider::{hetzner::HetznerProvider, CloudConfig, Guards},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HetznerConfig {
     pub name: String,
-    pub api_key: String,
+    pub api_key: Secret,
     pub location: String,
     pub server_type: String,
     pub image: String,
     pub username: Option<String>,
     pub ssh_key: String,          // Hetzner key name in account
     pub local_privat_key: String, // local private key path (typo preserved)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>, // defaults to the public Hetzner API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<u64>, // baked image (`cargo remote image bake`), used instead of `image`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_size: Option<u32>, // GB of the build cache volume, none without
+    #[serde(flatten)]
+    pub guards: Guards,
 }
 
 impl CloudConfig for HetznerConfig {
@@ -32,6 +41,10 @@ impl CloudConfig for HetznerConfig {
     fn private_key_path(&self) -> String {
         self.local_privat_key.clone()
     }
+
+    fn guards(&self) -> Guards {
+        self.guards.clone()
+    }
 }
 
 impl fmt::Display for HetznerConfig {
@@ -45,31 +58,36 @@ impl fmt::Display for HetznerConfig {
 }
 
 pub fn hetzner_config_wizzard(name: String) -> Result<SavedConfig> {
-    let api_key = Password::new("Hetzner API key")
-        .without_confirmation()
-        .prompt()?;
+    let (api_key_secret, api_key) =
+        secret_wizzard("Hetzner API key", "HCLOUD_TOKEN", "pass show hetzner")?;
     let hetzner_locations = HetznerProvider::get_locations(api_key.clone());
 
-    let location = if let Ok(locations) = hetzner_locations {
-        let selected = Select::new("Location: ", locations).prompt()?;
-        selected.name
-    } else {
-        eprintln!("***** We could not query the Locations. This could have many reasons. Your Api-Key could be wrong or your Computer may not be connected to the Internet. This may cause Problems later! *****");
-        Text::new("Location: ")
-            .with_initial_value("nbg1")
-            .prompt()?
+    let location = match hetzner_locations {
+        Ok(locations) => {
+            let selected = Select::new("Location: ", locations).prompt()?;
+            selected.name
+        }
+        Err(e) => {
+            eprintln!("***** We could not query the Locations ({:#}). Your Api-Key could be wrong or your Computer may not be connected to the Internet. This may cause Problems later! *****", e);
+            Text::new("Location: ")
+                .with_initial_value("nbg1")
+                .prompt()?
+        }
     };
 
     let server_types = HetznerProvider::get_server_types(api_key.clone());
-    let server_type = if let Ok(types) = server_types {
-        let selected = Select::new("Server type: ", types).prompt()?;
-        selected.name
-    } else {
-        eprintln!("***** We could not query the Server-types. This could have many reasons. Your Api-Key could be wrong or your Computer may not be connected to the Internet. This may cause Problems later! *****");
+    let server_type = match server_types {
+        Ok(types) => {
+            let selected = Select::new("Server type: ", types).prompt()?;
+            selected.name
+        }
+        Err(e) => {
+            eprintln!("***** We could not query the Server-types ({:#}). Your Api-Key could be wrong or your Computer may not be connected to the Internet. This may cause Problems later! *****", e);
 
-        Text::new("Server type: ")
-            .with_initial_value("cpx21")
-            .prompt()?
+            Text::new("Server type: ")
+                .with_initial_value("cpx21")
+                .prompt()?
+        }
     };
 
     let image = Text::new("Image (This tool will use apt to install rust and other dependencies! Ubuntu recommendet):")
@@ -92,17 +110,26 @@ pub fn hetzner_config_wizzard(name: String) -> Result<SavedConfig> {
 
     let local_privat_key = Text::new("Local SSH Private Key Path: ").prompt()?;
 
+    let volume_size = CustomType::<u32>::new(
+        "Build cache volume size in GB (kept between sessions and billed while it exists, empty for none):",
+    )
+    .prompt_skippable()?;
+
     Ok(SavedConfig {
         mode: Mode::Hetzner,
         data: ConfigData::Hetzner(HetznerConfig {
             name,
-            api_key,
+            api_key: api_key_secret,
             location,
             server_type,
             image,
             username: Some("root".to_string()),
             ssh_key,
             local_privat_key,
+            api_url: None,
+            snapshot: None,
+            volume_size,
+            guards: Guards::default(),
         }),
     })
 }
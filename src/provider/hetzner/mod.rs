@@ -1,20 +1,79 @@
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::provider::{hetzner::config::HetznerConfig, Provider, ProviderKind, ServerHandle};
-use anyhow::{anyhow, Result};
-use reqwest::blocking::Client;
+use crate::provider::cloud_init::{self, Recipe};
+use crate::provider::handle::ServerState;
+use crate::provider::http::{self, Api, Dialect};
+use crate::provider::{
+    creator, hetzner::config::HetznerConfig, listed, Existence, Provider, ProviderKind,
+    RentOptions, ServerHandle,
+};
+use anyhow::{anyhow, Context, Result};
+use log::warn;
 use serde::Deserialize;
 use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
 pub mod config;
+pub mod volume;
 
-pub struct HetznerProvider {
-    pub cfg: HetznerConfig,
+const DEFAULT_API_URL: &str = "https://api.hetzner.cloud/v1";
+const API_URL_ENV: &str = "CARGO_REMOTE_HETZNER_API_URL";
+const LABEL_TOOL: &str = "tool";
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Errors are `{"error": {"code": ..., "message": ...}}`, list responses name the next page
+static DIALECT: Dialect = Dialect {
+    name: "hetzner",
+    error: |v| {
+        let e = v.get("error")?;
+        Some((
+            e.get("code")?.as_str()?.to_string(),
+            e.get("message")?.as_str()?.to_string(),
+        ))
+    },
+    next_page: |v, _| {
+        v.pointer("/meta/pagination/next_page")?
+            .as_u64()
+            .map(|n| n as u32)
+    },
+};
+
+/// Hetzner label values: at most 63 alphanumeric characters, `-`, `_` and `.`
+fn label_value(s: &str) -> String {
+    let v: String = s
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(63)
+        .collect();
+    v.trim_matches(|c: char| !c.is_ascii_alphanumeric())
+        .to_string()
 }
 
-#[derive(Deserialize)]
-pub struct LoactionResponse {
-    locations: Vec<Location>,
+/// Gross hourly price of a server type (as in `/server_types`) in a location
+fn hourly_price(server_type: &Value, location: &str) -> Option<f64> {
+    server_type
+        .get("prices")?
+        .as_array()?
+        .iter()
+        .find(|p| p.get("location").and_then(|l| l.as_str()) == Some(location))?
+        .pointer("/price_hourly/gross")?
+        .as_str()?
+        .parse()
+        .ok()
+}
+
+#[derive(Clone)]
+pub struct HetznerProvider {
+    pub cfg: HetznerConfig,
+    api_key: String,
 }
 
 #[derive(Deserialize)]
@@ -24,11 +83,6 @@ pub struct Location {
     description: String,
 }
 
-#[derive(Deserialize)]
-pub struct SSHResponse {
-    ssh_keys: Vec<SSHKey>,
-}
-
 #[derive(Deserialize)]
 pub struct SSHKey {
     name: String,
@@ -36,8 +90,13 @@ pub struct SSHKey {
 }
 
 #[derive(Deserialize)]
-pub struct ServerTypesResponse {
-    server_types: Vec<ServerTypes>,
+pub struct Snapshot {
+    pub id: u64,
+    #[serde(default)]
+    pub description: String,
+    pub created: String,
+    /// Size in GB
+    pub image_size: Option<f64>,
 }
 
 #[derive(Deserialize)]
@@ -76,172 +135,314 @@ impl std::fmt::Display for SSHKey {
 }
 
 impl HetznerProvider {
-    fn client(&self) -> Client {
-        Client::builder().build().unwrap()
-    }
-    fn base() -> &'static str {
-        "https://api.hetzner.cloud/v1"
-    }
-    fn cloud_init(&self, preinstall: &[String]) -> String {
-        let mut s = String::from(
-            "#cloud-config\npackage_update: true\npackage_upgrade: true\npackages:\n\
- - build-essential\n\
- - gcc\n\
- - make\n\
- - musl\n\
- - musl-tools\n\
- - libssl-dev\n\
- - pkg-config\n\
- - llvm\n\
- - clang\n\
- - git\n\
- - curl\n\
- - ca-certificates\n",
-        );
-        for p in preinstall {
-            if !p.trim().is_empty() {
-                s.push_str(&format!(" - {}\n", p.trim()));
+    pub fn new(cfg: HetznerConfig) -> Result<Self> {
+        let api_key = cfg
+            .api_key
+            .resolve()
+            .with_context(|| format!("API key of `{}`", cfg.name))?;
+        Ok(Self { cfg, api_key })
+    }
+
+    pub fn api_url(configured: Option<&str>) -> String {
+        http::base_url(API_URL_ENV, configured, DEFAULT_API_URL)
+    }
+
+    fn client(base: String, api_key: &str) -> Result<Api> {
+        Api::new(base, format!("Bearer {}", api_key), &DIALECT)
+    }
+
+    fn base(&self) -> String {
+        Self::api_url(self.cfg.api_url.as_deref())
+    }
+    fn cloud_init(&self, opts: &RentOptions, volume: Option<u64>) -> String {
+        cloud_init::user_data(
+            opts,
+            &Recipe {
+                baked: self.cfg.snapshot.is_some(),
+                prelude: volume.map(Self::mount_volume).unwrap_or_default(),
+            },
+        )
+    }
+
+    /// Labels of rented Servers, see [`label_value`] for what Hetzner allows
+    fn labels(&self, project_key: &str) -> Value {
+        let (user, host) = creator();
+        serde_json::json!({
+            LABEL_TOOL: "cargo-remote",
+            "project": label_value(project_key),
+            "config": label_value(&self.cfg.name),
+            "created-by": label_value(&user),
+            "created-on": label_value(&host),
+        })
+    }
+
+    /// Builds a handle from a server object of the API
+    fn handle(&self, server: &Value) -> Result<ServerHandle> {
+        let id = server
+            .get("id")
+            .and_then(|x| x.as_i64())
+            .ok_or_else(|| anyhow!("missing id"))?
+            .to_string();
+        let ip = server
+            .get("public_net")
+            .and_then(|p| p.get("ipv4"))
+            .and_then(|i| i.get("ip"))
+            .and_then(|x| x.as_str())
+            .ok_or_else(|| anyhow!("missing IPv4"))?
+            .to_string();
+        let username = self.cfg.username.clone().unwrap_or("root".into());
+        let created_at = server
+            .get("created")
+            .and_then(|c| c.as_str())
+            .and_then(|c| OffsetDateTime::parse(c, &Rfc3339).ok())
+            .map(|c| c.unix_timestamp().max(0) as u64);
+        let location = server
+            .pointer("/datacenter/location/name")
+            .and_then(|l| l.as_str())
+            .unwrap_or(&self.cfg.location);
+        let server_type = server.get("server_type");
+        // Configs may share the account, the label names the one that rented it
+        let config = match server.pointer("/labels/config").and_then(|c| c.as_str()) {
+            Some(c) if c != label_value(&self.cfg.name) => c.to_string(),
+            _ => self.cfg.name.clone(),
+        };
+        Ok(ServerHandle {
+            provider: ProviderKind::Hetzner,
+            config,
+            id,
+            host: ip,
+            port: 22,
+            username,
+            created_at,
+            server_type: server_type
+                .and_then(|t| t.get("name"))
+                .and_then(|n| n.as_str())
+                .map(|n| n.to_string()),
+            hourly_price: server_type.and_then(|t| hourly_price(t, location)),
+            state: ServerState::Running,
+            idle_action: None,
+            ready_marker: true,
+        })
+    }
+
+    fn api(&self) -> Result<Api> {
+        Self::client(self.base(), &self.api_key)
+    }
+
+    /// Runs a server action (e.g. `shutdown`) and waits until it finished
+    fn action(&self, handle: &ServerHandle, action: &str, body: &Value) -> Result<Value> {
+        let api = self.api()?;
+        let v = api
+            .post(&format!("/servers/{}/actions/{}", handle.id, action), body)
+            .with_context(|| format!("hetzner {} failed", action))?;
+        if let Some(a) = v.get("action") {
+            self.wait_action(&api, a)?;
+        }
+        Ok(v)
+    }
+
+    fn wait_action(&self, api: &Api, action: &Value) -> Result<()> {
+        let id = action
+            .get("id")
+            .and_then(|x| x.as_i64())
+            .ok_or_else(|| anyhow!("missing action id"))?;
+        let mut action = action.clone();
+        loop {
+            match action.get("status").and_then(|s| s.as_str()) {
+                Some("success") => return Ok(()),
+                Some("error") => {
+                    let msg = action
+                        .pointer("/error/message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("unknown error");
+                    return Err(anyhow!("hetzner action {} failed: {}", id, msg));
+                }
+                _ => {}
             }
+            std::thread::sleep(Duration::from_secs(3));
+            action = api
+                .get(&format!("/actions/{}", id))?
+                .get("action")
+                .cloned()
+                .ok_or_else(|| anyhow!("missing action"))?;
         }
-        s.push_str(
-            "runcmd:
- - [bash, -lc, \"export DEBIAN_FRONTEND=noninteractive && apt-get update && apt-get -yq upgrade\"]
- - [bash, -lc, \"apt-get install -yqq curl ca-certificates\"]
- - [bash, -lc, \"curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs -o /root/rustup-init.sh\"]
- - [bash, -lc, \"chmod +x /root/rustup-init.sh\"]
- - [bash, -lc, \"/root/rustup-init.sh -y --profile minimal --default-toolchain stable\"]
- - [bash, -lc, \"echo 'export PATH=\\\"$HOME/.cargo/bin:$PATH\\\"' >> /root/.bashrc\"]
- - [bash, -lc, \"printf 'export PATH=\\\"/root/.cargo/bin:$PATH\\\"\\n' > /etc/profile.d/cargo.sh && chmod +x /etc/profile.d/cargo.sh\"]
- - [bash, -lc, \"/root/.cargo/bin/rustc --version && /root/.cargo/bin/cargo --version\"]
- - [bash, -lc, \"touch /root/ready\"]
-",
-        );
-        s
     }
 
-    pub fn get_server_types(api_key: String) -> Result<Vec<ServerTypes>> {
-        let client = Client::builder().build().unwrap();
-
-        let resp = client
-            .get(format!("{}/server_types", Self::base()))
-            .bearer_auth(api_key)
-            .send()?;
-        if !resp.status().is_success() {
-            let msg = resp.text().unwrap_or_default();
-            return Err(anyhow!("hetzner create failed: {}", msg));
+    /// Shuts the Server down gracefully, powers it off if it doesn't stop in time
+    pub fn shutdown(&self, handle: &ServerHandle) -> Result<()> {
+        self.action(handle, "shutdown", &serde_json::json!({}))?;
+        let start = Instant::now();
+        while self.status(handle)? != "off" {
+            if start.elapsed() >= SHUTDOWN_TIMEOUT {
+                self.action(handle, "poweroff", &serde_json::json!({}))?;
+                break;
+            }
+            std::thread::sleep(Duration::from_secs(3));
         }
+        Ok(())
+    }
 
-        let response: ServerTypesResponse = resp.json().unwrap();
+    /// Creates a snapshot of the Server and returns its image id
+    pub fn create_snapshot(&self, handle: &ServerHandle, description: &str) -> Result<u64> {
+        let body = serde_json::json!({
+            "type": "snapshot",
+            "description": description,
+            "labels": {
+                LABEL_TOOL: "cargo-remote",
+                "config": label_value(&self.cfg.name),
+            },
+        });
+        let v = self.action(handle, "create_image", &body)?;
+        v.pointer("/image/id")
+            .and_then(|x| x.as_u64())
+            .ok_or_else(|| anyhow!("missing image id"))
+    }
 
-        Ok(response.server_types)
+    /// All snapshots baked by cargo-remote in the account
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        let selector = format!("{}=cargo-remote", LABEL_TOOL);
+        self.api()?
+            .list(
+                "/images",
+                "images",
+                &[("type", "snapshot"), ("label_selector", &selector)],
+            )
+            .context("hetzner image list failed")
     }
 
-    pub fn get_ssh_keys(api_key: String) -> Result<Vec<SSHKey>> {
-        let client = Client::builder().build().unwrap();
-
-        let resp = client
-            .get(format!("{}/ssh_keys", Self::base()))
-            .bearer_auth(api_key)
-            .send()?;
-        if !resp.status().is_success() {
-            let msg = resp.text().unwrap_or_default();
-            return Err(anyhow!("hetzner create failed: {}", msg));
-        }
+    pub fn delete_image(&self, id: u64) -> Result<()> {
+        self.api()?
+            .delete(&format!("/images/{}", id))
+            .context("hetzner image delete failed")
+    }
 
-        let response: SSHResponse = resp.json().unwrap();
+    pub fn get_server_types(api_key: String) -> Result<Vec<ServerTypes>> {
+        Self::client(Self::api_url(None), &api_key)?.list("/server_types", "server_types", &[])
+    }
 
-        Ok(response.ssh_keys)
+    pub fn get_ssh_keys(api_key: String) -> Result<Vec<SSHKey>> {
+        Self::client(Self::api_url(None), &api_key)?.list("/ssh_keys", "ssh_keys", &[])
     }
 
     pub fn get_locations(api_key: String) -> Result<Vec<Location>> {
-        let client = Client::builder().build().unwrap();
-
-        let resp = client
-            .get(format!("{}/locations", Self::base()))
-            .bearer_auth(api_key)
-            .send()?;
-        if !resp.status().is_success() {
-            let msg = resp.text().unwrap_or_default();
-            return Err(anyhow!("hetzner create failed: {}", msg));
-        }
-
-        let response: LoactionResponse = resp.json().unwrap();
-
-        Ok(response.locations)
+        Self::client(Self::api_url(None), &api_key)?.list("/locations", "locations", &[])
     }
 }
 
 impl Provider for HetznerProvider {
-    fn rent(&self, project_key: &str, preinstall: &[String]) -> Result<ServerHandle> {
-        let client = self.client();
+    fn rent(&self, project_key: &str, opts: &RentOptions) -> Result<ServerHandle> {
         let timestampt = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs();
         let name = format!("cargo-remote-{}-{}", project_key, timestampt);
 
-        let body = serde_json::json!({
+        let volume = self.volume_for_rent()?;
+        let mut body = serde_json::json!({
             "name": name,
             "server_type": self.cfg.server_type,
-            "image": self.cfg.image,
+            "image": self.cfg.snapshot.map(|id| id.to_string()).unwrap_or(self.cfg.image.clone()),
             "location": self.cfg.location,
             "ssh_keys": [self.cfg.ssh_key.clone()],
-            "user_data": self.cloud_init(preinstall),
+            "user_data": self.cloud_init(opts, volume),
+            "labels": self.labels(project_key),
         });
-
-        let resp = client
-            .post(format!("{}/servers", Self::base()))
-            .bearer_auth(&self.cfg.api_key)
-            .json(&body)
-            .send()?;
-        if !resp.status().is_success() {
-            let msg = resp.text().unwrap_or_default();
-            return Err(anyhow!("hetzner create failed: {}", msg));
+        if let Some(id) = volume {
+            body["volumes"] = serde_json::json!([id]);
+            body["automount"] = serde_json::json!(false);
         }
-        let v: Value = resp.json()?;
+
+        let v = self
+            .api()?
+            .post("/servers", &body)
+            .context("hetzner create failed")?;
         let server = v.get("server").ok_or_else(|| anyhow!("missing server"))?;
-        let id = server
-            .get("id")
-            .and_then(|x| x.as_i64())
-            .ok_or_else(|| anyhow!("missing id"))?
-            .to_string();
-        let ip = server
-            .get("public_net")
-            .and_then(|p| p.get("ipv4"))
-            .and_then(|i| i.get("ip"))
-            .and_then(|x| x.as_str())
-            .ok_or_else(|| anyhow!("missing IPv4"))?
-            .to_string();
-        let username = self.cfg.username.clone().unwrap_or("root".into());
-        Ok(ServerHandle {
-            provider: ProviderKind::Hetzner,
-            config: self.cfg.name.clone(),
-            id,
-            host: ip,
-            port: 22,
-            username,
-        })
+        self.handle(server)
     }
 
     fn delete(&self, handle: &ServerHandle) -> Result<()> {
-        let client = self.client();
-        let resp = client
-            .delete(format!("{}/servers/{}", Self::base(), handle.id))
-            .bearer_auth(&self.cfg.api_key)
-            .send()?;
-        if !resp.status().is_success() {
-            let msg = resp.text().unwrap_or_default();
-            return Err(anyhow!("hetzner delete failed: {}", msg));
+        // Deleting the Server would detach it as well, but without unmounting it cleanly
+        if let Err(e) = self.release_volume(handle) {
+            warn!("could not detach the build cache: {:#}", e);
         }
+        self.api()?
+            .delete(&format!("/servers/{}", handle.id))
+            .context("hetzner delete failed")
+    }
+
+    fn exists(&self, handle: &ServerHandle) -> Existence {
+        let res = self
+            .api()
+            .and_then(|api| api.get(&format!("/servers/{}", handle.id)));
+        match res {
+            Ok(_) => Existence::Exists,
+            Err(e) if http::is_not_found(&e) => Existence::Gone,
+            Err(e) => Existence::Unknown(format!("{:#}", e)),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<ServerHandle>> {
+        let selector = format!("{}=cargo-remote", LABEL_TOOL);
+        let servers: Vec<Value> = self
+            .api()?
+            .list("/servers", "servers", &[("label_selector", &selector)])
+            .context("hetzner list failed")?;
+        Ok(servers
+            .iter()
+            .filter_map(|s| listed(&s["id"], self.handle(s)))
+            .collect())
+    }
+
+    fn find(&self, id_or_name: &str) -> Result<ServerHandle> {
+        let api = self.api()?;
+        if id_or_name.chars().all(|c| c.is_ascii_digit()) {
+            let v = api
+                .get(&format!("/servers/{}", id_or_name))
+                .context("hetzner find failed")?;
+            let server = v.get("server").ok_or_else(|| anyhow!("missing server"))?;
+            return self.handle(server);
+        }
+        let servers: Vec<Value> = api
+            .list("/servers", "servers", &[("name", id_or_name)])
+            .context("hetzner find failed")?;
+        let server = servers
+            .first()
+            .ok_or_else(|| anyhow!("no server named `{}`", id_or_name))?;
+        self.handle(server)
+    }
+
+    fn power_off(&self, handle: &ServerHandle) -> Result<()> {
+        self.shutdown(handle)
+    }
+
+    fn power_on(&self, handle: &ServerHandle) -> Result<()> {
+        self.action(handle, "poweron", &serde_json::json!({}))?;
         Ok(())
     }
 
-    fn exists(&self, handle: &ServerHandle) -> Result<bool> {
-        let client = self.client();
-        let resp = client
-            .get(format!("{}/servers/{}", Self::base(), handle.id))
-            .bearer_auth(&self.cfg.api_key)
-            .send()?;
-        Ok(resp.status().is_success())
+    fn hourly_price(&self) -> Result<Option<f64>> {
+        let types: Vec<Value> = self
+            .api()?
+            .list(
+                "/server_types",
+                "server_types",
+                &[("name", &self.cfg.server_type)],
+            )
+            .context("hetzner server type lookup failed")?;
+        Ok(types
+            .first()
+            .and_then(|t| hourly_price(t, &self.cfg.location)))
+    }
+
+    fn status(&self, handle: &ServerHandle) -> Result<String> {
+        let v = self
+            .api()?
+            .get(&format!("/servers/{}", handle.id))
+            .context("hetzner status failed")?;
+        v.get("server")
+            .and_then(|s| s.get("status"))
+            .and_then(|s| s.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| anyhow!("missing status"))
     }
 }
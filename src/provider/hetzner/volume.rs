@@ -0,0 +1,155 @@
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use serde::Deserialize;
+
+use crate::provider::hetzner::{label_value, HetznerProvider, LABEL_TOOL};
+use crate::provider::{Provider, ServerHandle};
+
+/// Where the volume is mounted, `remote-builds` and `.cargo` are bind mounts into it
+const MOUNT: &str = "/mnt/cargo-remote";
+
+#[derive(Debug, Deserialize)]
+pub struct VolumeLocation {
+    pub name: String,
+}
+
+/// A build cache volume, kept between sessions of one config
+#[derive(Debug, Deserialize)]
+pub struct Volume {
+    pub id: u64,
+    pub name: String,
+    /// Size in GB
+    pub size: u32,
+    pub location: VolumeLocation,
+    /// Id of the Server it is attached to
+    pub server: Option<u64>,
+    pub created: String,
+}
+
+impl HetznerProvider {
+    fn volume_name(&self) -> String {
+        format!("cargo-remote-{}", label_value(&self.cfg.name))
+    }
+
+    /// The cache volume of this config, if it was created already
+    pub fn volume(&self) -> Result<Option<Volume>> {
+        let name = self.volume_name();
+        let volumes: Vec<Volume> = self
+            .api()?
+            .list("/volumes", "volumes", &[("name", &name)])
+            .context("hetzner volume list failed")?;
+        Ok(volumes.into_iter().next())
+    }
+
+    /// The cache volume to attach to a new Server. Created on first use; `None` if the config
+    /// has no cache or it can't be used right now (e.g. attached to another Server)
+    pub(super) fn volume_for_rent(&self) -> Result<Option<u64>> {
+        let Some(size) = self.cfg.volume_size else {
+            return Ok(None);
+        };
+        if let Some(v) = self.volume()? {
+            if let Some(server) = v.server {
+                warn!(
+                    "build cache {} is attached to server {}, starting without it",
+                    v.name, server
+                );
+                return Ok(None);
+            }
+            if v.location.name != self.cfg.location {
+                warn!(
+                    "build cache {} is in {}, not {}, starting without it",
+                    v.name, v.location.name, self.cfg.location
+                );
+                return Ok(None);
+            }
+            return Ok(Some(v.id));
+        }
+
+        let body = serde_json::json!({
+            "name": self.volume_name(),
+            "size": size,
+            "location": self.cfg.location,
+            "format": "ext4",
+            "automount": false,
+            "labels": {
+                LABEL_TOOL: "cargo-remote",
+                "config": label_value(&self.cfg.name),
+            },
+        });
+        let api = self.api()?;
+        let v = api
+            .post("/volumes", &body)
+            .context("hetzner volume create failed")?;
+        if let Some(a) = v.get("action") {
+            self.wait_action(&api, a)?;
+        }
+        v.pointer("/volume/id")
+            .and_then(|x| x.as_u64())
+            .map(Some)
+            .ok_or_else(|| anyhow!("missing volume id"))
+    }
+
+    /// Detaches the cache volume from the Server before it is deleted. The Server is shut down
+    /// first, so the filesystem is unmounted cleanly
+    pub(super) fn release_volume(&self, handle: &ServerHandle) -> Result<()> {
+        if self.cfg.volume_size.is_none() {
+            return Ok(());
+        }
+        let Some(v) = self.volume()? else {
+            return Ok(());
+        };
+        if v.server.map(|s| s.to_string()) != Some(handle.id.clone()) {
+            return Ok(());
+        }
+        if self.status(handle)? == "running" {
+            self.shutdown(handle)?;
+        }
+        let api = self.api()?;
+        let res = api
+            .post(
+                &format!("/volumes/{}/actions/detach", v.id),
+                &serde_json::json!({}),
+            )
+            .context("hetzner volume detach failed")?;
+        if let Some(a) = res.get("action") {
+            self.wait_action(&api, a)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the cache volume. It must not be attached
+    pub fn destroy_volume(&self, v: &Volume) -> Result<()> {
+        if let Some(server) = v.server {
+            return Err(anyhow!(
+                "volume {} is attached to server {}, end that session first",
+                v.name,
+                server
+            ));
+        }
+        self.api()?
+            .delete(&format!("/volumes/{}", v.id))
+            .context("hetzner volume delete failed")
+    }
+
+    /// `runcmd` entries mounting the volume as `/root/remote-builds` and `/root/.cargo`. A
+    /// toolchain that is already installed (snapshot) is moved to a fresh volume
+    pub(super) fn mount_volume(id: u64) -> String {
+        let dev = format!("/dev/disk/by-id/scsi-0HC_Volume_{}", id);
+        let fstab = format!(
+            "{dev} {m} ext4 discard,nofail,defaults 0 0\\n\
+             {m}/remote-builds /root/remote-builds none bind,nofail 0 0\\n\
+             {m}/cargo /root/.cargo none bind,nofail 0 0\\n",
+            dev = dev,
+            m = MOUNT
+        );
+        format!(
+            " - [bash, -lc, \"udevadm settle; mkdir -p {m} && mount -o discard,defaults {dev} {m}\"]
+ - [bash, -lc, \"mkdir -p /root/.cargo /root/remote-builds {m}/remote-builds && ([ -d {m}/cargo ] || cp -a /root/.cargo {m}/cargo)\"]
+ - [bash, -lc, \"printf '{fstab}' >> /etc/fstab && mount /root/remote-builds && mount /root/.cargo\"]
+",
+            m = MOUNT,
+            dev = dev,
+            fstab = fstab
+        )
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Retries on HTTP 429 (and 503 of idempotent requests) before giving up
+const MAX_RETRIES: u32 = 5;
+/// Longest we wait for a rate limit reset
+const MAX_BACKOFF: u64 = 60;
+const PER_PAGE: u32 = 50;
+
+/// What differs between the JSON APIs of the providers
+pub struct Dialect {
+    /// Used in messages, e.g. `hetzner`
+    pub name: &'static str,
+    /// Code and message of an error response body
+    pub error: fn(&Value) -> Option<(String, String)>,
+    /// The page after `page` of a list response, `None` on the last one
+    pub next_page: fn(&Value, u32) -> Option<u32>,
+}
+
+/// A non-success response of a provider API
+#[derive(Debug)]
+pub struct ApiError {
+    pub api: &'static str,
+    pub status: u16,
+    pub code: String,
+    pub message: String,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} API error {} ({}): {}",
+            self.api, self.status, self.code, self.message
+        )
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ApiError {
+    fn from_response(dialect: &Dialect, resp: Response) -> Self {
+        let status = resp.status().as_u16();
+        let text = resp.text().unwrap_or_default();
+        let (code, message) = serde_json::from_str(&text)
+            .ok()
+            .and_then(|v| (dialect.error)(&v))
+            .unwrap_or_else(|| ("unknown".into(), text.trim().to_string()));
+        ApiError {
+            api: dialect.name,
+            status,
+            code,
+            message,
+        }
+    }
+}
+
+/// Thin client for the JSON API of a provider
+pub struct Api {
+    client: Client,
+    base: String,
+    /// Value of the `Authorization` header
+    auth: String,
+    dialect: &'static Dialect,
+}
+
+impl Api {
+    pub fn new(base: String, auth: String, dialect: &'static Dialect) -> Result<Self> {
+        Ok(Self {
+            client: Client::builder().build()?,
+            base,
+            auth,
+            dialect,
+        })
+    }
+
+    pub fn get(&self, path: &str) -> Result<Value> {
+        self.json(Method::GET, path, |r| r)
+    }
+
+    pub fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        self.json(Method::POST, path, |r| r.json(body))
+    }
+
+    pub fn delete(&self, path: &str) -> Result<()> {
+        self.send(Method::DELETE, path, |r| r)?;
+        Ok(())
+    }
+
+    /// Fetches all pages of a list endpoint, e.g. `list("/ssh_keys", "ssh_keys", &[])`
+    pub fn list<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        key: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut page = Some(1);
+        while let Some(p) = page {
+            let v = self.json(Method::GET, path, |r| {
+                r.query(query).query(&[("page", p), ("per_page", PER_PAGE)])
+            })?;
+            let list = v
+                .get(key)
+                .cloned()
+                .ok_or_else(|| anyhow!("{} {}: missing `{}`", self.dialect.name, path, key))?;
+            items.extend(serde_json::from_value::<Vec<T>>(list)?);
+            page = (self.dialect.next_page)(&v, p);
+        }
+        Ok(items)
+    }
+
+    fn json(
+        &self,
+        method: Method,
+        path: &str,
+        build: impl FnOnce(RequestBuilder) -> RequestBuilder,
+    ) -> Result<Value> {
+        let resp = self.send(method, path, build)?;
+        resp.json()
+            .map_err(|e| anyhow!("{} {}: invalid response: {}", self.dialect.name, path, e))
+    }
+
+    /// Sends the request, retrying when rate limited. Non-success responses become [`ApiError`].
+    /// A 503 may come after a `POST` created the Server, retrying it could rent a second one
+    fn send(
+        &self,
+        method: Method,
+        path: &str,
+        build: impl FnOnce(RequestBuilder) -> RequestBuilder,
+    ) -> Result<Response> {
+        let idempotent = method == Method::GET || method == Method::DELETE;
+        let req = build(
+            self.client
+                .request(method, format!("{}{}", self.base, path)),
+        )
+        .header(AUTHORIZATION, &self.auth);
+        let mut attempt = 0;
+        loop {
+            let resp = req
+                .try_clone()
+                .ok_or_else(|| anyhow!("request can't be retried"))?
+                .send()?;
+            let status = resp.status();
+            if status.is_success() {
+                return Ok(resp);
+            }
+            let retry = status == StatusCode::TOO_MANY_REQUESTS
+                || (idempotent && status == StatusCode::SERVICE_UNAVAILABLE);
+            if !retry || attempt >= MAX_RETRIES {
+                return Err(ApiError::from_response(self.dialect, resp).into());
+            }
+            let wait = backoff(&resp, attempt);
+            warn!(
+                "{} API returned {}, retrying in {}s",
+                self.dialect.name,
+                status.as_u16(),
+                wait.as_secs()
+            );
+            std::thread::sleep(wait);
+            attempt += 1;
+        }
+    }
+}
+
+/// The API base URL: the `env` variable, then the configured `api_url`, then `default`. Mostly
+/// useful to test against a stand-in server.
+pub fn base_url(env: &str, configured: Option<&str>, default: &str) -> String {
+    std::env::var(env)
+        .ok()
+        .filter(|u| !u.is_empty())
+        .or_else(|| configured.map(|u| u.to_string()))
+        .unwrap_or_else(|| default.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Whether `e` is a 404 of a provider API
+pub fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<ApiError>()
+        .is_some_and(|e| e.status == 404)
+}
+
+/// `Retry-After` (seconds), the time until `RateLimit-Reset` (a unix timestamp), otherwise
+/// exponential backoff
+fn backoff(resp: &Response, attempt: u32) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let header = |name: &str| {
+        resp.headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.trim().parse::<u64>().ok())
+    };
+    let secs = header("Retry-After")
+        .or_else(|| header("RateLimit-Reset").map(|reset| reset.saturating_sub(now)))
+        .unwrap_or(1 << attempt);
+    Duration::from_secs(secs.clamp(1, MAX_BACKOFF))
+}
@@ -1,20 +1,98 @@
 use crate::cmds::configure::manual::manual_wizzard;
 use crate::config::ManualConfig;
 use crate::config::{mode::ConfigData, mode::Mode, SavedConfig};
-use crate::provider::handle::ServerHandle;
+use crate::provider::digitalocean::config::{digitalocean_config_wizzard, DigitalOceanConfig};
+use crate::provider::digitalocean::DigitalOceanProvider;
+use crate::provider::ec2::config::{ec2_config_wizzard, Ec2Config};
+use crate::provider::ec2::Ec2Provider;
+use crate::provider::exec::config::{exec_config_wizzard, ExecConfig};
+use crate::provider::exec::ExecProvider;
+use crate::provider::handle::{ServerHandle, ServerState};
 use crate::provider::hetzner::config::{hetzner_config_wizzard, HetznerConfig};
+use crate::state::State;
 use anyhow::{anyhow, Result};
+use clap::ValueEnum;
 use enum_dispatch::enum_dispatch;
 use hetzner::HetznerProvider;
+use log::warn;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
+pub mod cloud_init;
+pub mod digitalocean;
+pub mod ec2;
+pub mod exec;
 pub mod handle;
 pub mod hetzner;
+pub mod http;
+
+/// What the Server should be set up with
+#[derive(Debug, Clone, Default)]
+pub struct RentOptions {
+    /// Additional packages
+    pub preinstall: Vec<String>,
+    /// Shut the Server down after some time without builds
+    pub idle: Option<IdleWatchdog>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IdleWatchdog {
+    pub timeout: Duration,
+    pub action: IdleAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdleAction {
+    /// Power the Server off (it is still billed, but keeps its disk)
+    Poweroff,
+    /// Power the Server off, the next `cargo remote status` deletes it. Disk and IPv4 are billed
+    /// until then
+    #[serde(alias = "delete")]
+    PoweroffThenReap,
+}
+
+/// Limits of a cloud config, checked before renting another Server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Guards {
+    /// Servers of this config at the same time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_servers: Option<u32>,
+    /// Servers running longer are reported by `begin` and `status`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_hours: Option<f64>,
+    /// Refuse to rent once Servers of this config cost that much this month
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_budget: Option<f64>,
+}
+
+/// Whether a rented Server still exists at the provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Existence {
+    Exists,
+    /// The provider confirmed that the Server is gone (e.g. HTTP 404)
+    Gone,
+    /// Couldn't be determined (auth failure, network outage, ...). Never prune on this
+    Unknown(String),
+}
 
 pub trait Provider {
-    fn rent(&self, project_key: &str, preinstall: &[String]) -> Result<ServerHandle>;
+    fn rent(&self, project_key: &str, opts: &RentOptions) -> Result<ServerHandle>;
     fn delete(&self, handle: &ServerHandle) -> Result<()>;
-    fn exists(&self, handle: &ServerHandle) -> Result<bool>;
+    fn exists(&self, handle: &ServerHandle) -> Existence;
+    /// All Servers created by cargo-remote in the account, whether tracked or not. Found by the
+    /// labels / tags set by `rent`, so `cargo remote gc` works even without `servers.toml`
+    fn list(&self) -> Result<Vec<ServerHandle>>;
+    /// Looks up any Server of the account by id or name
+    fn find(&self, id_or_name: &str) -> Result<ServerHandle>;
+    /// The provider specific status of the Server (e.g. `initializing`, `running`, `off`)
+    fn status(&self, handle: &ServerHandle) -> Result<String>;
+    /// Shuts the Server down, keeping its disk
+    fn power_off(&self, handle: &ServerHandle) -> Result<()>;
+    /// Boots a powered off Server again
+    fn power_on(&self, handle: &ServerHandle) -> Result<()>;
+    /// Hourly price of a new Server, if the provider publishes it
+    fn hourly_price(&self) -> Result<Option<f64>>;
 }
 
 #[enum_dispatch]
@@ -22,11 +100,16 @@ pub trait CloudConfig {
     fn name(&self) -> &str;
 
     fn private_key_path(&self) -> String;
+
+    fn guards(&self) -> Guards;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ProviderKind {
     Hetzner,
+    Exec,
+    DigitalOcean,
+    Ec2,
 }
 
 #[allow(clippy::to_string_trait_impl)]
@@ -34,6 +117,9 @@ impl ToString for ProviderKind {
     fn to_string(&self) -> String {
         match self {
             ProviderKind::Hetzner => "Hetzner",
+            ProviderKind::Exec => "Exec",
+            ProviderKind::DigitalOcean => "DigitalOcean",
+            ProviderKind::Ec2 => "EC2",
         }
         .to_string()
     }
@@ -44,17 +130,111 @@ impl Mode {
         match self {
             Mode::Manual => manual_wizzard(name),
             Mode::Hetzner => hetzner_config_wizzard(name),
+            Mode::Exec => exec_config_wizzard(name),
+            Mode::DigitalOcean => digitalocean_config_wizzard(name),
+            Mode::Ec2 => ec2_config_wizzard(name),
         }
     }
 }
 
+/// Builds the provider of a cloud config. Its credentials are resolved here, they may be
+/// references to an environment variable, file or command
 pub fn get_provider(c: &SavedConfig) -> Result<Box<dyn Provider>> {
     match (&c.mode, &c.data) {
-        (Mode::Hetzner, ConfigData::Hetzner(h)) => Ok(Box::new(HetznerProvider { cfg: h.clone() })),
+        (Mode::Hetzner, ConfigData::Hetzner(h)) => Ok(Box::new(HetznerProvider::new(h.clone())?)),
+        (Mode::Exec, ConfigData::Exec(e)) => Ok(Box::new(ExecProvider { cfg: e.clone() })),
+        (Mode::DigitalOcean, ConfigData::DigitalOcean(d)) => {
+            Ok(Box::new(DigitalOceanProvider::new(d.clone())?))
+        }
+        (Mode::Ec2, ConfigData::Ec2(e)) => Ok(Box::new(Ec2Provider { cfg: e.clone() })),
         _ => Err(anyhow!("unsupported provider for this config")),
     }
 }
 
-pub fn provider_exists(c: &SavedConfig, h: &ServerHandle) -> Result<bool> {
-    get_provider(c)?.exists(h)
+/// A Server of `Provider::list`, `None` with a warning if it can't be used (e.g. no IPv4 yet)
+pub fn listed(id: impl std::fmt::Display, handle: Result<ServerHandle>) -> Option<ServerHandle> {
+    handle
+        .map_err(|e| warn!("skipping server {}: {:#}", id, e))
+        .ok()
+}
+
+/// User and machine renting a Server, recorded in its labels / tags
+pub fn creator() -> (String, String) {
+    let user = std::env::var("USER").unwrap_or_default();
+    let host = std::fs::read_to_string("/etc/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+    (user.trim().to_string(), host.trim().to_string())
+}
+
+/// Refuses to rent if a guard of the config is exceeded, warns if it is close
+pub fn check_guards(c: &SavedConfig, provider: &dyn Provider, st: &State) -> Result<()> {
+    let guards = c.data.guards();
+    let active: Vec<&ServerHandle> = st
+        .projects
+        .iter()
+        .filter(|h| h.config == c.name())
+        .collect();
+
+    if let Some(max) = guards.max_servers {
+        if active.len() >= max as usize {
+            return Err(anyhow!(
+                "`{}` already has {} server(s) (max_servers = {}); end one with `cargo remote end`",
+                c.name(),
+                active.len(),
+                max
+            ));
+        }
+    }
+
+    if let Some(max) = guards.max_hours {
+        for h in active.iter() {
+            let hours = h.uptime().map(|u| u.as_secs_f64() / 3600.0).unwrap_or(0.0);
+            if hours > max {
+                warn!(
+                    "server {} (id={}) is running for {:.1}h (max_hours = {})",
+                    h.host, h.id, hours, max
+                );
+            }
+        }
+    }
+
+    if let Some(budget) = guards.monthly_budget {
+        let spent = st.monthly_cost(c.name());
+        if spent >= budget {
+            return Err(anyhow!(
+                "`{}` reached its monthly budget ({:.2} of {:.2})",
+                c.name(),
+                spent,
+                budget
+            ));
+        }
+        if let Some(price) = provider.hourly_price()? {
+            let hours = guards.max_hours.unwrap_or(1.0).max(1.0).ceil();
+            if spent + price * hours > budget {
+                warn!(
+                    "`{}` spent {:.2} of its monthly budget of {:.2}, another server costs {:.4}/h",
+                    c.name(),
+                    spent,
+                    budget,
+                    price
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Powers a paused Server on. Some providers (e.g. EC2) assign a new IP on boot, `h.host`
+/// is updated from the provider then
+pub fn resume(provider: &dyn Provider, h: &mut ServerHandle) -> Result<()> {
+    provider.power_on(h)?;
+    if let Ok(found) = provider.find(&h.id) {
+        if !found.host.is_empty() {
+            h.host = found.host;
+        }
+    }
+    h.state = ServerState::Running;
+    Ok(())
 }
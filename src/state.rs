@@ -1,11 +1,28 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 
 use crate::provider::handle::ServerHandle;
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct State {
     pub projects: Vec<ServerHandle>,
+    /// Costs of deleted Servers, for the monthly budget
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spent: Vec<Spent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spent {
+    pub config: String,
+    pub month: String, // `YYYY-MM`
+    pub cost: f64,
+}
+
+/// The current month as `YYYY-MM`
+pub fn current_month() -> String {
+    let now = OffsetDateTime::now_utc();
+    format!("{:04}-{:02}", now.year(), u8::from(now.month()))
 }
 
 impl State {
@@ -30,4 +47,39 @@ impl State {
         std::fs::write(p, s).context("write state")?;
         Ok(())
     }
+
+    /// Stops tracking a deleted Server and books its cost
+    pub fn remove(&mut self, id: &str) {
+        let month = current_month();
+        for h in self.projects.iter().filter(|h| h.id == id) {
+            if let Some(cost) = h.cost() {
+                self.spent.push(Spent {
+                    config: h.config.clone(),
+                    month: month.clone(),
+                    cost,
+                });
+            }
+        }
+        self.projects.retain(|h| h.id != id);
+        // Only the current month matters for the budget
+        self.spent.retain(|s| s.month == month);
+    }
+
+    /// Spent this month on Servers of the config, including the ones still running
+    pub fn monthly_cost(&self, config: &str) -> f64 {
+        let month = current_month();
+        let spent: f64 = self
+            .spent
+            .iter()
+            .filter(|s| s.config == config && s.month == month)
+            .map(|s| s.cost)
+            .sum();
+        let running: f64 = self
+            .projects
+            .iter()
+            .filter(|h| h.config == config)
+            .filter_map(|h| h.cost())
+            .sum();
+        spent + running
+    }
 }
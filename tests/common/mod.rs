@@ -0,0 +1,213 @@
+//! Helpers shared by the integration tests: a throwaway project and XDG config dir, and a tiny
+//! HTTP server standing in for provider APIs
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+
+/// A temporary Cargo project with its own `XDG_CONFIG_HOME`
+pub struct TestEnv {
+    pub dir: PathBuf,
+    pub xdg: PathBuf,
+    pub project: PathBuf,
+    /// An (empty) private key file, `begin` only checks that it exists
+    pub key: PathBuf,
+}
+
+impl TestEnv {
+    pub fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("cargo-remote-{}-test-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let xdg = dir.join("xdg");
+        let project = dir.join("project");
+        std::fs::create_dir_all(xdg.join("cargo-remote")).unwrap();
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(
+            project.join("Cargo.toml"),
+            "[package]\nname = \"p\"\nversion = \"0.1.0\"\nedition = \"2018\"\n",
+        )
+        .unwrap();
+        std::fs::write(project.join("src/main.rs"), "fn main() {}\n").unwrap();
+        let key = dir.join("id_test");
+        std::fs::write(&key, "").unwrap();
+        TestEnv {
+            dir,
+            xdg,
+            project,
+            key,
+        }
+    }
+
+    pub fn write_config(&self, toml: &str) {
+        std::fs::write(self.xdg.join("cargo-remote/config.toml"), toml).unwrap();
+    }
+
+    /// Used instead of `/etc/cargo-remote/config.toml`, doesn't exist unless a test writes it
+    pub fn system_config(&self) -> PathBuf {
+        self.dir.join("system-config.toml")
+    }
+
+    pub fn servers(&self) -> String {
+        std::fs::read_to_string(self.xdg.join("cargo-remote/servers.toml")).unwrap_or_default()
+    }
+
+    pub fn cargo_remote(&self, args: &[&str], envs: &[(&str, &str)]) -> Output {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_cargo-remote"));
+        // Don't pick up credentials or API overrides of the developer
+        for (k, _) in std::env::vars() {
+            if k.starts_with("AWS_") || k.starts_with("CARGO_REMOTE_") {
+                cmd.env_remove(k);
+            }
+        }
+        cmd.arg("remote")
+            .args(args)
+            .current_dir(&self.project)
+            .env("XDG_CONFIG_HOME", &self.xdg)
+            .env("CARGO_REMOTE_SYSTEM_CONFIG", self.system_config())
+            .envs(envs.iter().copied())
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Whether `rsync` is installed, tests of the sync pipeline are skipped without it
+pub fn has_rsync() -> bool {
+    Command::new("rsync").arg("--version").output().is_ok()
+}
+
+pub fn assert_success(out: &Output) {
+    assert!(
+        out.status.success(),
+        "stdout:\n{}\nstderr:\n{}",
+        String::from_utf8_lossy(&out.stdout),
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Path including the query string
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// HTTP/1.1 server on a random local port. Every request is logged and answered by the
+/// handler with `(status, json body)`, or `(status, headers, json body)` with `start_with_headers`
+pub struct MockServer {
+    pub url: String,
+    pub requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> (u16, String) + Send + 'static,
+    {
+        Self::start_with_headers(move |req: &Request| {
+            let (status, body) = handler(req);
+            (status, Vec::new(), body)
+        })
+    }
+
+    pub fn start_with_headers<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> (u16, Vec<(String, String)>, String) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let Some(req) = read_request(&mut stream) else {
+                    continue;
+                };
+                let (status, headers, body) = handler(&req);
+                log.lock().unwrap().push(req);
+                let headers: String = headers
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}\r\n", k, v))
+                    .collect();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    headers,
+                    body
+                );
+            }
+        });
+        MockServer { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn read_request(stream: &mut impl Read) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (k, v) = line.split_once(':')?;
+        headers.push((k.trim().to_string(), v.trim().to_string()));
+    }
+    let len = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// Path of an example binary, e.g. `target/<profile>/examples/<name>`, built by `cargo test`
+pub fn example(name: &str) -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    exe.parent()
+        .and_then(|deps| deps.parent())
+        .unwrap()
+        .join("examples")
+        .join(name)
+}
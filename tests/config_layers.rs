@@ -0,0 +1,154 @@
+mod common;
+
+use common::{assert_success, TestEnv};
+
+fn stdout(out: &std::process::Output) -> String {
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+#[test]
+fn layers_merge_items_by_name() {
+    let env = TestEnv::new("config-layers");
+    std::fs::write(
+        env.system_config(),
+        "priority = \"manual\"\n\n[[items]]\nmode = \"manual\"\nname = \"team\"\nuser = \"build\"\nhost = \"build.example.com\"\nport = 22\nssh_public_key_path = \"/keys/team.pub\"\nssh_private_key_path = \"/keys/team\"\n",
+    )
+    .unwrap();
+    env.write_config(
+        "default = \"team\"\n\n[[items]]\nmode = \"manual\"\nname = \"mine\"\nuser = \"me\"\nhost = \"10.0.0.2\"\nport = 22\nssh_public_key_path = \"/keys/me.pub\"\nssh_private_key_path = \"/keys/me\"\n",
+    );
+    let dropins = env.xdg.join("cargo-remote/config.d");
+    std::fs::create_dir_all(&dropins).unwrap();
+    std::fs::write(
+        dropins.join("10-port.toml"),
+        "[[items]]\nname = \"team\"\nport = 2222\n",
+    )
+    .unwrap();
+    std::fs::write(dropins.join("notes.txt"), "not a config").unwrap();
+
+    let out = env.cargo_remote(&["config", "list"], &[]);
+    assert_success(&out);
+    let list = stdout(&out);
+    assert!(list.contains("0 [*] team manual"), "{}", list);
+    assert!(list.contains("1 [ ] mine manual"), "{}", list);
+
+    let out = env.cargo_remote(&["config", "show", "--effective"], &[]);
+    assert_success(&out);
+    let shown = stdout(&out);
+    let line = |prefix: &str| {
+        shown
+            .lines()
+            .find(|l| l.starts_with(prefix))
+            .unwrap_or_else(|| panic!("no `{}` in\n{}", prefix, shown))
+            .to_string()
+    };
+    let system = env.system_config().display().to_string();
+    assert!(
+        line("port = 2222").ends_with("config.d/10-port.toml"),
+        "{}",
+        shown
+    );
+    assert!(
+        line("host = \"build.example.com\"").ends_with(&system),
+        "{}",
+        shown
+    );
+    assert!(
+        line("priority = \"manual\"").ends_with(&system),
+        "{}",
+        shown
+    );
+    assert!(
+        line("default = \"team\"").ends_with("cargo-remote/config.toml"),
+        "{}",
+        shown
+    );
+
+    // Only the user config is written, the team host can't be deleted from there
+    let out = env.cargo_remote(&["config", "delete", "--name", "team"], &[]);
+    assert!(!out.status.success());
+    let out = env.cargo_remote(&["config", "delete", "--index", "1"], &[]);
+    assert_success(&out);
+    let user = std::fs::read_to_string(env.xdg.join("cargo-remote/config.toml")).unwrap();
+    assert!(!user.contains("mine"), "{}", user);
+    assert!(!user.contains("2222"), "{}", user);
+}
+
+#[test]
+fn manual_host_from_environment() {
+    let env = TestEnv::new("config-env");
+    env.write_config(
+        "default = \"mine\"\n\n[[items]]\nmode = \"manual\"\nname = \"mine\"\nuser = \"me\"\nhost = \"10.0.0.2\"\nport = 22\nssh_public_key_path = \"/keys/me.pub\"\nssh_private_key_path = \"/keys/me\"\n",
+    );
+    let vars = [
+        ("CARGO_REMOTE_HOST", "ci-builder.internal"),
+        ("CARGO_REMOTE_PORT", "2200"),
+        ("CARGO_REMOTE_KEY", "/run/secrets/ci_key"),
+    ];
+
+    let out = env.cargo_remote(&["config", "list"], &vars);
+    assert_success(&out);
+    let list = stdout(&out);
+    assert!(list.contains("[*] env manual"), "{}", list);
+    assert!(list.contains("[ ] mine manual"), "{}", list);
+
+    let out = env.cargo_remote(&["config", "show", "--effective", "--name", "env"], &vars);
+    assert_success(&out);
+    let shown = stdout(&out);
+    for (key, origin) in [
+        ("host = \"ci-builder.internal\"", "$CARGO_REMOTE_HOST"),
+        ("port = 2200", "$CARGO_REMOTE_PORT"),
+        (
+            "ssh_private_key_path = \"/run/secrets/ci_key\"",
+            "$CARGO_REMOTE_KEY",
+        ),
+        (
+            "ssh_public_key_path = \"/run/secrets/ci_key.pub\"",
+            "default",
+        ),
+        ("user = \"root\"", "default"),
+        ("mode = \"manual\"", "default"),
+    ] {
+        assert!(
+            shown
+                .lines()
+                .any(|l| l.starts_with(key) && l.ends_with(&format!("# {}", origin))),
+            "`{}` from {} missing in\n{}",
+            key,
+            origin,
+            shown
+        );
+    }
+
+    // Without CARGO_REMOTE_HOST the variables only change an existing item
+    let out = env.cargo_remote(&["config", "list"], &[("CARGO_REMOTE_USER", "ci")]);
+    assert_success(&out);
+    let list = stdout(&out);
+    assert!(list.contains("[*] mine manual"), "{}", list);
+    assert!(!list.contains("] env "), "{}", list);
+
+    let vars = [("CARGO_REMOTE_USER", "ci"), ("CARGO_REMOTE_NAME", "mine")];
+    let out = env.cargo_remote(&["config", "show", "--effective", "--name", "mine"], &vars);
+    assert_success(&out);
+    let shown = stdout(&out);
+    assert!(
+        shown
+            .lines()
+            .any(|l| l.starts_with("user = \"ci\"") && l.ends_with("# $CARGO_REMOTE_USER")),
+        "{}",
+        shown
+    );
+
+    let out = env.cargo_remote(&["config", "list"], &[("CARGO_REMOTE_PORT", "ssh")]);
+    assert!(!out.status.success());
+
+    // A broken config is reported, not taken for an empty one
+    env.write_config("[[items]]\nmode = \"manual\"\nname = \"mine\"\n");
+    let out = env.cargo_remote(&["status"], &[]);
+    assert!(!out.status.success());
+    assert!(
+        stdout(&out).contains("config `mine` is incomplete or invalid"),
+        "{}",
+        stdout(&out)
+    );
+}
@@ -0,0 +1,278 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::{assert_success, MockServer, Request, TestEnv};
+
+const DROPLET_ID: u64 = 3164444;
+
+fn droplet(networks: &str, status: &str) -> String {
+    format!(
+        r#"{{"id":{},"name":"cargo-remote-p","status":"{}","created_at":"2026-01-01T10:00:00Z","size_slug":"s-4vcpu-8gb","size":{{"price_hourly":0.07143}},"networks":{{"v4":{}}},"tags":["cargo-remote"]}}"#,
+        DROPLET_ID, status, networks
+    )
+}
+
+/// Stand-in for the parts of the DigitalOcean API used by the provider
+fn mock_api(exists: Arc<Mutex<bool>>) -> MockServer {
+    mock_api_with_status(exists, Arc::new(Mutex::new("active")))
+}
+
+fn mock_api_with_status(exists: Arc<Mutex<bool>>, status: Arc<Mutex<&'static str>>) -> MockServer {
+    let polled = Arc::new(Mutex::new(0));
+    MockServer::start(move |req: &Request| {
+        if req.header("authorization") != Some("Bearer do-token") {
+            return (
+                401,
+                r#"{"id":"unauthorized","message":"Unable to authenticate you"}"#.into(),
+            );
+        }
+        let droplet_path = format!("/droplets/{}", DROPLET_ID);
+        let path = req.path.split('?').next().unwrap();
+        match (req.method.as_str(), path) {
+            ("POST", "/droplets") => {
+                *exists.lock().unwrap() = true;
+                (202, format!(r#"{{"droplet":{}}}"#, droplet("[]", "new")))
+            }
+            ("GET", "/droplets") if *exists.lock().unwrap() => (
+                200,
+                format!(
+                    r#"{{"droplets":[{}],"links":{{}},"meta":{{"total":1}}}}"#,
+                    droplet(
+                        r#"[{"ip_address":"203.0.113.7","type":"public"}]"#,
+                        *status.lock().unwrap()
+                    )
+                ),
+            ),
+            ("GET", "/droplets") => (200, r#"{"droplets":[],"links":{},"meta":{"total":0}}"#.into()),
+            ("GET", p) if p == droplet_path && *exists.lock().unwrap() => {
+                // The public IP shows up once the Droplet is booting
+                let mut polled = polled.lock().unwrap();
+                *polled += 1;
+                let networks = if *polled > 1 {
+                    r#"[{"ip_address":"10.0.0.2","type":"private"},{"ip_address":"203.0.113.7","type":"public"}]"#
+                } else {
+                    "[]"
+                };
+                (
+                    200,
+                    format!(
+                        r#"{{"droplet":{}}}"#,
+                        droplet(networks, *status.lock().unwrap())
+                    ),
+                )
+            }
+            ("DELETE", p) if p == droplet_path => {
+                *exists.lock().unwrap() = false;
+                (204, String::new())
+            }
+            _ => (
+                404,
+                r#"{"id":"not_found","message":"The resource you were accessing could not be found."}"#
+                    .into(),
+            ),
+        }
+    })
+}
+
+#[test]
+fn digitalocean_rents_tags_and_prunes() {
+    let env = TestEnv::new("digitalocean");
+    let exists = Arc::new(Mutex::new(false));
+    let api = mock_api(exists.clone());
+    let api_env = [("CARGO_REMOTE_DIGITALOCEAN_API_URL", api.url.as_str())];
+
+    env.write_config(&format!(
+        "[[items]]\nmode = \"digitalocean\"\nname = \"do\"\napi_token = \"do-token\"\nregion = \"fra1\"\nsize = \"s-4vcpu-8gb\"\nimage = \"ubuntu-22-04-x64\"\nusername = \"root\"\nssh_key = \"3b:16:bf\"\nprivate_key = {:?}\n",
+        env.key
+    ));
+
+    let out = env.cargo_remote(&["begin", "--config", "do"], &api_env);
+    assert_success(&out);
+    let servers = env.servers();
+    assert!(
+        servers.contains("provider = \"DigitalOcean\""),
+        "{}",
+        servers
+    );
+    assert!(
+        servers.contains(&format!("id = \"{}\"", DROPLET_ID)),
+        "{}",
+        servers
+    );
+    assert!(servers.contains("host = \"203.0.113.7\""), "{}", servers);
+    assert!(
+        servers.contains("server_type = \"s-4vcpu-8gb\""),
+        "{}",
+        servers
+    );
+
+    let requests = api.requests();
+    let create = requests
+        .iter()
+        .find(|r| r.method == "POST" && r.path == "/droplets")
+        .expect("no create request");
+    let body: serde_json::Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(body["region"], "fra1");
+    assert_eq!(body["ssh_keys"][0], "3b:16:bf");
+    assert!(body["user_data"].as_str().unwrap().contains("rustup"));
+    let tags: Vec<&str> = body["tags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t.as_str().unwrap())
+        .collect();
+    assert!(tags.contains(&"cargo-remote"), "{:?}", tags);
+    assert!(tags.contains(&"cargo-remote-config:do"), "{:?}", tags);
+    assert!(
+        tags.iter().any(|t| t.starts_with("cargo-remote-project:")),
+        "{:?}",
+        tags
+    );
+
+    // Tracked, so it isn't reported as orphaned
+    let out = env.cargo_remote(&["gc"], &api_env);
+    assert_success(&out);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("No orphaned servers"));
+
+    // Deleted behind our back: the API answers 404 and `status` stops tracking it
+    *exists.lock().unwrap() = false;
+    let out = env.cargo_remote(&["status"], &api_env);
+    assert_success(&out);
+    assert!(!env.servers().contains(&DROPLET_ID.to_string()));
+}
+
+#[test]
+fn digitalocean_unknown_on_auth_failure() {
+    let env = TestEnv::new("digitalocean-auth");
+    let api = mock_api(Arc::new(Mutex::new(true)));
+    let api_env = [("CARGO_REMOTE_DIGITALOCEAN_API_URL", api.url.as_str())];
+
+    env.write_config(&format!(
+        "[[items]]\nmode = \"digitalocean\"\nname = \"do\"\napi_token = \"revoked\"\nregion = \"fra1\"\nsize = \"s-4vcpu-8gb\"\nimage = \"ubuntu-22-04-x64\"\nssh_key = \"3b:16:bf\"\nprivate_key = {:?}\n",
+        env.key
+    ));
+    std::fs::write(
+        env.xdg.join("cargo-remote/servers.toml"),
+        format!(
+            "[[projects]]\nprovider = \"DigitalOcean\"\nconfig = \"do\"\nid = \"{}\"\nhost = \"203.0.113.7\"\nport = 22\nusername = \"root\"\n",
+            DROPLET_ID
+        ),
+    )
+    .unwrap();
+
+    // A 401 must never be mistaken for a deleted Droplet
+    let out = env.cargo_remote(&["status"], &api_env);
+    assert_success(&out);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("unknown, keeping it"));
+    assert!(env.servers().contains(&DROPLET_ID.to_string()));
+}
+
+#[test]
+fn digitalocean_token_by_reference() {
+    let env = TestEnv::new("digitalocean-secret");
+    let api = mock_api(Arc::new(Mutex::new(false)));
+    let api_env = [
+        ("CARGO_REMOTE_DIGITALOCEAN_API_URL", api.url.as_str()),
+        ("DO_TOKEN", "do-token"),
+    ];
+    let config = |token: &str| {
+        format!(
+            "[[items]]\nmode = \"digitalocean\"\nname = \"do\"\napi_token = {}\nregion = \"fra1\"\nsize = \"s-4vcpu-8gb\"\nimage = \"ubuntu-22-04-x64\"\nssh_key = \"3b:16:bf\"\nprivate_key = {:?}\n",
+            token, env.key
+        )
+    };
+
+    // Resolved when the provider is created
+    for token in [
+        r#"{ env = "DO_TOKEN" }"#,
+        r#"{ command = "printf 'do-token\nlogin: me\n'" }"#,
+    ] {
+        env.write_config(&config(token));
+        let out = env.cargo_remote(&["gc"], &api_env);
+        assert_success(&out);
+        assert!(String::from_utf8_lossy(&out.stdout).contains("No orphaned servers"));
+    }
+
+    let out = env.cargo_remote(&["config", "show"], &api_env);
+    assert_success(&out);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("printf"));
+
+    // An unresolvable token is reported, the other configs are still checked
+    env.write_config(&format!(
+        "{}{}",
+        config(r#"{ env = "MISSING_TOKEN" }"#).replace("name = \"do\"", "name = \"broken\""),
+        config(r#"{ env = "DO_TOKEN" }"#)
+    ));
+    let out = env.cargo_remote(&["gc"], &api_env);
+    assert_success(&out);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("[DigitalOcean-broken] could not use the config"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("MISSING_TOKEN"), "{}", stdout);
+    assert!(stdout.contains("No orphaned servers"), "{}", stdout);
+
+    // Literal tokens are never printed
+    env.write_config(&config("\"do-token\""));
+    let out = env.cargo_remote(&["config", "show"], &api_env);
+    assert_success(&out);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!stdout.contains("do-token"), "{}", stdout);
+    assert!(stdout.contains("<redacted>"), "{}", stdout);
+}
+
+#[test]
+fn digitalocean_idle_watchdog_is_reconciled_by_status() {
+    let env = TestEnv::new("digitalocean-idle");
+    let exists = Arc::new(Mutex::new(false));
+    let status = Arc::new(Mutex::new("active"));
+    let api = mock_api_with_status(exists.clone(), status.clone());
+    let api_env = [("CARGO_REMOTE_DIGITALOCEAN_API_URL", api.url.as_str())];
+    env.write_config(&format!(
+        "[[items]]\nmode = \"digitalocean\"\nname = \"do\"\napi_token = \"do-token\"\nregion = \"fra1\"\nsize = \"s-4vcpu-8gb\"\nimage = \"ubuntu-22-04-x64\"\nssh_key = \"3b:16:bf\"\nprivate_key = {:?}\n",
+        env.key
+    ));
+
+    for action in ["poweroff-then-reap", "poweroff"] {
+        *status.lock().unwrap() = "active";
+        let out = env.cargo_remote(
+            &[
+                "begin",
+                "--config",
+                "do",
+                "--idle-timeout",
+                "30m",
+                "--idle-action",
+                action,
+            ],
+            &api_env,
+        );
+        assert_success(&out);
+        let create = api
+            .requests()
+            .into_iter()
+            .rfind(|r| r.method == "POST" && r.path == "/droplets")
+            .expect("no create request");
+        let body: serde_json::Value = serde_json::from_str(&create.body).unwrap();
+        let user_data = body["user_data"].as_str().unwrap();
+        assert!(user_data.contains("systemctl poweroff"), "{}", user_data);
+        // The API token never ends up on the Droplet
+        assert!(!user_data.contains("do-token"), "{}", user_data);
+
+        // Powered off by the watchdog
+        *status.lock().unwrap() = "off";
+        let out = env.cargo_remote(&["status"], &api_env);
+        assert_success(&out);
+        let servers = env.servers();
+        if action == "poweroff" {
+            assert!(servers.contains("state = \"paused\""), "{}", servers);
+            assert!(*exists.lock().unwrap());
+        } else {
+            assert!(!servers.contains(&DROPLET_ID.to_string()), "{}", servers);
+            assert!(!*exists.lock().unwrap());
+        }
+    }
+}
@@ -0,0 +1,313 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use common::{assert_success, MockServer, Request, TestEnv};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+const INSTANCE_ID: &str = "i-0123456789abcdef0";
+const ACCESS_KEY: &str = "AKIDEXAMPLE";
+const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+
+#[derive(Default)]
+struct Ec2State {
+    state: Option<&'static str>,
+    ip: &'static str,
+}
+
+fn form(body: &str) -> HashMap<String, String> {
+    body.split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (decode(k), decode(v)))
+        .collect()
+}
+
+fn decode(s: &str) -> String {
+    let mut out = Vec::new();
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            out.push(u8::from_str_radix(&s[i + 1..i + 3], 16).unwrap());
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).unwrap()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Checks the SigV4 signature the way EC2 does, from the request as it arrived
+fn signature_valid(req: &Request) -> bool {
+    let Some(auth) = req.header("authorization") else {
+        return false;
+    };
+    let Some(rest) = auth.strip_prefix("AWS4-HMAC-SHA256 ") else {
+        return false;
+    };
+    let parts: HashMap<&str, &str> = rest.split(", ").filter_map(|p| p.split_once('=')).collect();
+    let (Some(credential), Some(signed), Some(signature)) = (
+        parts.get("Credential"),
+        parts.get("SignedHeaders"),
+        parts.get("Signature"),
+    ) else {
+        return false;
+    };
+    let scope_parts: Vec<&str> = credential.split('/').collect();
+    if scope_parts.len() != 5 || scope_parts[0] != ACCESS_KEY {
+        return false;
+    }
+    let scope = scope_parts[1..].join("/");
+
+    let canonical_headers: String = signed
+        .split(';')
+        .map(|h| format!("{}:{}\n", h, req.header(h).unwrap_or_default().trim()))
+        .collect();
+    let canonical = format!(
+        "{}\n/\n\n{}\n{}\n{}",
+        req.method,
+        canonical_headers,
+        signed,
+        hex(&Sha256::digest(req.body.as_bytes()))
+    );
+    let to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        req.header("x-amz-date").unwrap_or_default(),
+        scope,
+        hex(&Sha256::digest(canonical.as_bytes()))
+    );
+    let mut key = format!("AWS4{}", SECRET_KEY).into_bytes();
+    for part in &scope_parts[1..] {
+        key = hmac(&key, part);
+    }
+    hex(&hmac(&key, &to_sign)) == *signature
+}
+
+fn error(code: &str, message: &str) -> String {
+    format!(
+        "<Response><Errors><Error><Code>{}</Code><Message>{}</Message></Error></Errors><RequestID>r</RequestID></Response>",
+        code, message
+    )
+}
+
+fn instance_xml(state: &str, ip: &str) -> String {
+    let ip = if ip.is_empty() {
+        String::new()
+    } else {
+        format!("<ipAddress>{}</ipAddress>", ip)
+    };
+    format!(
+        "<item><instanceId>{}</instanceId><instanceState><code>16</code><name>{}</name></instanceState><instanceType>c7i.4xlarge</instanceType><launchTime>2026-01-01T10:00:00.000Z</launchTime>{}</item>",
+        INSTANCE_ID, state, ip
+    )
+}
+
+/// Stand-in for the EC2 query API, keeping a single instance
+fn mock_ec2(ec2: Arc<Mutex<Ec2State>>) -> MockServer {
+    MockServer::start(move |req: &Request| {
+        if !signature_valid(req) {
+            return (
+                403,
+                error("SignatureDoesNotMatch", "signature doesn't match"),
+            );
+        }
+        let params = form(&req.body);
+        let mut ec2 = ec2.lock().unwrap();
+        let ns = r#"xmlns="http://ec2.amazonaws.com/doc/2016-11-15/""#;
+        match params.get("Action").map(|a| a.as_str()) {
+            Some("RunInstances") => {
+                ec2.state = Some("running");
+                ec2.ip = "203.0.113.7";
+                (
+                    200,
+                    format!(
+                        "<RunInstancesResponse {}><instancesSet>{}</instancesSet></RunInstancesResponse>",
+                        ns,
+                        instance_xml("pending", "")
+                    ),
+                )
+            }
+            Some("DescribeInstances") => {
+                let by_id = params.get("InstanceId.1");
+                match ec2.state {
+                    None if by_id.is_some() => (
+                        400,
+                        error("InvalidInstanceID.NotFound", "instance does not exist"),
+                    ),
+                    None => (
+                        200,
+                        format!("<DescribeInstancesResponse {}><reservationSet/></DescribeInstancesResponse>", ns),
+                    ),
+                    Some(state) => (
+                        200,
+                        format!(
+                            "<DescribeInstancesResponse {}><reservationSet><item><instancesSet>{}</instancesSet></item></reservationSet></DescribeInstancesResponse>",
+                            ns,
+                            instance_xml(state, ec2.ip)
+                        ),
+                    ),
+                }
+            }
+            Some("StopInstances") => {
+                ec2.state = Some("stopped");
+                ec2.ip = "";
+                (200, format!("<StopInstancesResponse {}/>", ns))
+            }
+            Some("StartInstances") => {
+                ec2.state = Some("running");
+                ec2.ip = "198.51.100.9";
+                (200, format!("<StartInstancesResponse {}/>", ns))
+            }
+            Some("TerminateInstances") => {
+                ec2.state = None;
+                (200, format!("<TerminateInstancesResponse {}/>", ns))
+            }
+            _ => (400, error("InvalidAction", "unknown action")),
+        }
+    })
+}
+
+fn config(env: &TestEnv, extra: &str) -> String {
+    format!(
+        "[[items]]\nmode = \"ec2\"\nname = \"aws\"\nregion = \"eu-central-1\"\ninstance_type = \"c7i.4xlarge\"\nimage = \"ami-0faab6bdbac9486fb\"\nkey_name = \"laptop\"\nsecurity_group = \"sg-0a1b2c\"\nusername = \"root\"\nprivate_key = {:?}\n{}",
+        env.key, extra
+    )
+}
+
+#[test]
+fn ec2_spot_instance_from_profile() {
+    let env = TestEnv::new("ec2-spot");
+    let ec2 = Arc::new(Mutex::new(Ec2State::default()));
+    let api = mock_ec2(ec2.clone());
+    let credentials = env.dir.join("aws-credentials");
+    std::fs::write(
+        &credentials,
+        format!(
+            "[default]\naws_access_key_id = wrong\naws_secret_access_key = wrong\n\n[ci]\naws_access_key_id = {}\naws_secret_access_key = {}\n",
+            ACCESS_KEY, SECRET_KEY
+        ),
+    )
+    .unwrap();
+    let credentials = credentials.to_string_lossy().into_owned();
+    let missing = env.dir.join("missing").to_string_lossy().into_owned();
+    let api_env = [
+        ("CARGO_REMOTE_EC2_API_URL", api.url.as_str()),
+        ("AWS_SHARED_CREDENTIALS_FILE", credentials.as_str()),
+        ("AWS_CONFIG_FILE", missing.as_str()),
+    ];
+    env.write_config(&config(&env, "profile = \"ci\"\nspot = true\n"));
+
+    let out = env.cargo_remote(&["begin", "--config", "aws"], &api_env);
+    assert_success(&out);
+    let servers = env.servers();
+    assert!(servers.contains("provider = \"Ec2\""), "{}", servers);
+    assert!(
+        servers.contains(&format!("id = \"{}\"", INSTANCE_ID)),
+        "{}",
+        servers
+    );
+    assert!(servers.contains("host = \"203.0.113.7\""), "{}", servers);
+
+    let run = api
+        .requests()
+        .into_iter()
+        .map(|r| form(&r.body))
+        .find(|p| p.get("Action").map(|a| a.as_str()) == Some("RunInstances"))
+        .expect("no RunInstances request");
+    assert_eq!(run["InstanceMarketOptions.MarketType"], "spot");
+    assert_eq!(run["InstanceInitiatedShutdownBehavior"], "terminate");
+    assert_eq!(run["KeyName"], "laptop");
+    assert_eq!(run["SecurityGroupId.1"], "sg-0a1b2c");
+    let user_data = base64::engine::general_purpose::STANDARD
+        .decode(&run["UserData"])
+        .unwrap();
+    let user_data = String::from_utf8(user_data).unwrap();
+    assert!(user_data.contains("disable_root: false"), "{}", user_data);
+    assert!(user_data.contains("rustup"), "{}", user_data);
+    let tags: HashMap<&str, &str> = (1..=6)
+        .filter_map(|i| {
+            Some((
+                run.get(&format!("TagSpecification.1.Tag.{}.Key", i))?
+                    .as_str(),
+                run.get(&format!("TagSpecification.1.Tag.{}.Value", i))?
+                    .as_str(),
+            ))
+        })
+        .collect();
+    assert_eq!(tags["tool"], "cargo-remote");
+    assert_eq!(tags["config"], "aws");
+    assert!(!tags["project"].is_empty());
+
+    // Tracked, so it isn't reported as orphaned
+    let out = env.cargo_remote(&["gc"], &api_env);
+    assert_success(&out);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("No orphaned servers"));
+
+    // Spot instances are ended, not paused
+    let out = env.cargo_remote(&["pause"], &api_env);
+    assert!(!out.status.success());
+
+    // Terminated behind our back: `status` stops tracking it
+    ec2.lock().unwrap().state = Some("terminated");
+    let out = env.cargo_remote(&["status"], &api_env);
+    assert_success(&out);
+    assert!(!env.servers().contains(INSTANCE_ID));
+}
+
+#[test]
+fn ec2_pause_resume_gets_new_ip() {
+    let env = TestEnv::new("ec2-pause");
+    let ec2 = Arc::new(Mutex::new(Ec2State::default()));
+    let api = mock_ec2(ec2.clone());
+    let missing = env.dir.join("missing").to_string_lossy().into_owned();
+    let api_env = [
+        ("CARGO_REMOTE_EC2_API_URL", api.url.as_str()),
+        ("AWS_ACCESS_KEY_ID", ACCESS_KEY),
+        ("AWS_SECRET_ACCESS_KEY", SECRET_KEY),
+        ("AWS_SESSION_TOKEN", "session-token"),
+        ("AWS_SHARED_CREDENTIALS_FILE", missing.as_str()),
+        ("AWS_CONFIG_FILE", missing.as_str()),
+    ];
+    env.write_config(&config(&env, ""));
+
+    let out = env.cargo_remote(&["begin", "--config", "aws"], &api_env);
+    assert_success(&out);
+    let requests = api.requests();
+    assert!(requests
+        .iter()
+        .all(|r| r.header("x-amz-security-token") == Some("session-token")));
+    let run = form(&requests[0].body);
+    assert_eq!(run["InstanceInitiatedShutdownBehavior"], "stop");
+    assert!(!run.contains_key("InstanceMarketOptions.MarketType"));
+
+    let out = env.cargo_remote(&["pause"], &api_env);
+    assert_success(&out);
+    assert!(env.servers().contains("state = \"paused\""));
+
+    // A stopped instance gets a new public IP when it is started again
+    let out = env.cargo_remote(&["resume"], &api_env);
+    assert_success(&out);
+    let servers = env.servers();
+    assert!(servers.contains("host = \"198.51.100.9\""), "{}", servers);
+    assert!(!servers.contains("paused"), "{}", servers);
+
+    // `exists` treats a 404 like error as gone
+    ec2.lock().unwrap().state = None;
+    let out = env.cargo_remote(&["status"], &api_env);
+    assert_success(&out);
+    assert!(!env.servers().contains(INSTANCE_ID));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{assert_success, example, TestEnv};
+
+#[test]
+fn exec_provider_rents_lists_and_prunes() {
+    let env = TestEnv::new("exec");
+    let state = env.dir.join("plugin.json");
+
+    env.write_config(&format!(
+        "[[items]]\nmode = \"exec\"\nname = \"plug\"\nexecutable = {:?}\nprivate_key = {:?}\n\n[items.settings]\nstate = {:?}\n",
+        example("exec_provider_plugin"),
+        env.key,
+        state
+    ));
+
+    let out = env.cargo_remote(&["begin", "--config", "plug"], &[]);
+    assert_success(&out);
+    let servers = env.servers();
+    assert!(servers.contains("provider = \"Exec\""), "{}", servers);
+    assert!(servers.contains("id = \"1\""), "{}", servers);
+    // The plugin gets the cloud-init user data, builds only wait for it if the plugin says so
+    assert!(servers.contains("ready_marker = false"), "{}", servers);
+    let plugin_state = std::fs::read_to_string(&state).unwrap();
+    assert!(plugin_state.contains("#cloud-config"), "{}", plugin_state);
+    // Outside of /root, so non-root ssh users can check it
+    assert!(
+        plugin_state.contains("touch /var/lib/cargo-remote/ready"),
+        "{}",
+        plugin_state
+    );
+
+    // Tracked, so it isn't reported as orphaned
+    let out = env.cargo_remote(&["gc"], &[]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("No orphaned servers"));
+
+    // Deleted behind our back: `status` confirms it's gone and stops tracking it
+    std::fs::write(&state, "[]").unwrap();
+    let out = env.cargo_remote(&["status"], &[]);
+    assert_success(&out);
+    let servers = env.servers();
+    assert!(!servers.contains("id = \"1\""), "{}", servers);
+}
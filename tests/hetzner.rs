@@ -0,0 +1,485 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use common::{MockServer, Request, TestEnv};
+
+const SERVER: &str = r#"{"id":42,"name":"cargo-remote-p-1767261600","status":"off","created":"2026-01-01T10:00:00+00:00","public_net":{"ipv4":{"ip":"203.0.113.7"}},"server_type":{"name":"cx22","prices":[{"location":"fsn1","price_hourly":{"net":"0.0060","gross":"0.0071"}}]},"datacenter":{"location":{"name":"fsn1"}},"labels":{"tool":"cargo-remote","config":"hz"}}"#;
+
+/// How the stand-in API answers the next requests
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Reply {
+    Ok,
+    ClientError,
+    ServerError,
+    Malformed,
+}
+
+/// What the failing replies look like in the output
+const FAILURES: [(Reply, &str); 3] = [
+    (Reply::ClientError, "insufficient permissions"),
+    (Reply::ServerError, "internal server error"),
+    (Reply::Malformed, "invalid response"),
+];
+
+/// Stand-in for the parts of the Hetzner Cloud API used by the provider
+fn mock_api(reply: Arc<Mutex<Reply>>, exists: Arc<Mutex<bool>>) -> MockServer {
+    MockServer::start(move |req: &Request| {
+        if req.header("authorization") != Some("Bearer hetzner-token") {
+            return (
+                401,
+                r#"{"error":{"code":"unauthorized","message":"unable to authenticate"}}"#.into(),
+            );
+        }
+        let ok = match *reply.lock().unwrap() {
+            Reply::Ok => true,
+            Reply::ClientError => {
+                return (
+                    403,
+                    r#"{"error":{"code":"forbidden","message":"insufficient permissions for this request"}}"#.into(),
+                )
+            }
+            Reply::ServerError => {
+                return (
+                    500,
+                    r#"{"error":{"code":"server_error","message":"internal server error"}}"#
+                        .into(),
+                )
+            }
+            Reply::Malformed => false,
+        };
+        let path = req.path.split('?').next().unwrap();
+        let mut exists = exists.lock().unwrap();
+        match (req.method.as_str(), path) {
+            ("POST", "/servers") if ok => {
+                *exists = true;
+                (
+                    201,
+                    format!(r#"{{"server":{},"root_password":null}}"#, SERVER),
+                )
+            }
+            ("GET", "/servers") if ok => {
+                let servers = if *exists { SERVER } else { "" };
+                (
+                    200,
+                    format!(
+                        r#"{{"servers":[{}],"meta":{{"pagination":{{"page":1,"next_page":null}}}}}}"#,
+                        servers
+                    ),
+                )
+            }
+            ("GET", "/servers/42") if ok && *exists => (200, format!(r#"{{"server":{}}}"#, SERVER)),
+            ("DELETE", "/servers/42") if *exists => {
+                *exists = false;
+                let body = if ok {
+                    r#"{"action":{"id":1,"command":"delete_server","status":"running"}}"#
+                } else {
+                    r#"{"action":"#
+                };
+                (200, body.into())
+            }
+            (_, "/servers") | (_, "/servers/42") if !ok => (200, r#"{"server":{"id":4"#.into()),
+            _ => (
+                404,
+                r#"{"error":{"code":"not_found","message":"server with ID '42' not found"}}"#
+                    .into(),
+            ),
+        }
+    })
+}
+
+struct Fixture {
+    env: TestEnv,
+    api: MockServer,
+    reply: Arc<Mutex<Reply>>,
+    exists: Arc<Mutex<bool>>,
+}
+
+/// A project with the Hetzner config `hz`
+fn hetzner_env(name: &str) -> TestEnv {
+    let env = TestEnv::new(name);
+    env.write_config(&format!(
+        "[[items]]\nmode = \"hetzner\"\nname = \"hz\"\napi_key = \"hetzner-token\"\nlocation = \"fsn1\"\nserver_type = \"cx22\"\nimage = \"ubuntu-24.04\"\nssh_key = \"me\"\nlocal_privat_key = {:?}\n",
+        env.key
+    ));
+    env
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let env = hetzner_env(name);
+        let reply = Arc::new(Mutex::new(Reply::Ok));
+        let exists = Arc::new(Mutex::new(false));
+        let api = mock_api(reply.clone(), exists.clone());
+        Fixture {
+            env,
+            api,
+            reply,
+            exists,
+        }
+    }
+
+    fn cargo_remote(&self, args: &[&str], reply: Reply) -> (Option<i32>, String) {
+        *self.reply.lock().unwrap() = reply;
+        let out = self.env.cargo_remote(
+            args,
+            &[("CARGO_REMOTE_HETZNER_API_URL", self.api.url.as_str())],
+        );
+        let output = format!(
+            "{}{}",
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        );
+        (out.status.code(), output)
+    }
+
+    /// Tracks the Server in `servers.toml` as if `begin` rented it
+    fn track(&self) {
+        *self.exists.lock().unwrap() = true;
+        std::fs::write(
+            self.env.xdg.join("cargo-remote/servers.toml"),
+            "[[projects]]\nprovider = \"Hetzner\"\nconfig = \"hz\"\nid = \"42\"\nhost = \"203.0.113.7\"\nport = 22\nusername = \"root\"\n",
+        )
+        .unwrap();
+    }
+
+    fn tracked(&self) -> bool {
+        self.env.servers().contains("id = \"42\"")
+    }
+}
+
+#[test]
+fn hetzner_begin_tracks_only_rented_servers() {
+    let f = Fixture::new("hetzner-begin");
+
+    for (reply, msg) in FAILURES {
+        let (code, out) = f.cargo_remote(&["begin", "--config", "hz"], reply);
+        assert_eq!(code, Some(3), "{:?}: {}", reply, out);
+        assert!(out.contains(msg), "{:?}: {}", reply, out);
+        assert!(!f.tracked(), "{:?}: {}", reply, f.env.servers());
+    }
+
+    let (code, out) = f.cargo_remote(&["begin", "--config", "hz"], Reply::Ok);
+    assert_eq!(code, Some(0), "{}", out);
+    let servers = f.env.servers();
+    assert!(servers.contains("provider = \"Hetzner\""), "{}", servers);
+    assert!(servers.contains("config = \"hz\""), "{}", servers);
+    assert!(f.tracked(), "{}", servers);
+    assert!(servers.contains("host = \"203.0.113.7\""), "{}", servers);
+    assert!(servers.contains("server_type = \"cx22\""), "{}", servers);
+    assert!(servers.contains("hourly_price = 0.0071"), "{}", servers);
+
+    let requests = f.api.requests();
+    let create = requests
+        .iter()
+        .rfind(|r| r.method == "POST" && r.path == "/servers")
+        .expect("no create request");
+    let body: serde_json::Value = serde_json::from_str(&create.body).unwrap();
+    assert_eq!(body["server_type"], "cx22");
+    assert_eq!(body["location"], "fsn1");
+    assert_eq!(body["image"], "ubuntu-24.04");
+    assert_eq!(body["ssh_keys"][0], "me");
+    assert_eq!(body["labels"]["tool"], "cargo-remote");
+    assert_eq!(body["labels"]["config"], "hz");
+    let user_data = body["user_data"].as_str().unwrap();
+    assert!(user_data.contains("rustup"));
+    assert!(!user_data.contains("hetzner-token"));
+}
+
+#[test]
+fn hetzner_status_only_prunes_deleted_servers() {
+    let f = Fixture::new("hetzner-status");
+    f.track();
+
+    // Failures never count as deleted
+    for (reply, msg) in FAILURES {
+        let (code, out) = f.cargo_remote(&["status"], reply);
+        assert_eq!(code, Some(0), "{:?}: {}", reply, out);
+        assert!(out.contains("unknown, keeping it"), "{:?}: {}", reply, out);
+        assert!(out.contains(msg), "{:?}: {}", reply, out);
+        assert!(f.tracked(), "{:?}: {}", reply, f.env.servers());
+    }
+
+    // Powered off (e.g. by the idle watchdog)
+    let (code, out) = f.cargo_remote(&["status"], Reply::Ok);
+    assert_eq!(code, Some(0), "{}", out);
+    assert!(out.contains("marked as paused"), "{}", out);
+    assert!(f.env.servers().contains("state = \"paused\""));
+
+    // Only a 404 means it is gone
+    *f.exists.lock().unwrap() = false;
+    let (code, out) = f.cargo_remote(&["status"], Reply::Ok);
+    assert_eq!(code, Some(0), "{}", out);
+    assert!(out.contains("deleted, removing it"), "{}", out);
+    assert!(!f.tracked(), "{}", f.env.servers());
+}
+
+#[test]
+fn hetzner_end_keeps_servers_it_could_not_delete() {
+    let f = Fixture::new("hetzner-end");
+    f.track();
+
+    let (code, out) = f.cargo_remote(&["end", "--server-id", "7"], Reply::Ok);
+    assert_eq!(code, Some(3), "{}", out);
+    assert!(out.contains("no matching session"), "{}", out);
+
+    for (reply, msg) in &FAILURES[..2] {
+        let (code, out) = f.cargo_remote(&["end", "--server-id", "42"], *reply);
+        assert_eq!(code, Some(3), "{:?}: {}", reply, out);
+        assert!(out.contains(msg), "{:?}: {}", reply, out);
+        assert!(f.tracked(), "{:?}: {}", reply, f.env.servers());
+    }
+
+    // The body of a successful delete isn't needed
+    let (code, out) = f.cargo_remote(&["end", "--server-id", "42"], Reply::Malformed);
+    assert_eq!(code, Some(0), "{}", out);
+    assert!(!f.tracked(), "{}", f.env.servers());
+
+    f.track();
+    let (code, out) = f.cargo_remote(&["end", "--config", "hz"], Reply::Ok);
+    assert_eq!(code, Some(0), "{}", out);
+    assert!(out.contains("Deleted 203.0.113.7 (id=42)"), "{}", out);
+    assert!(!f.tracked(), "{}", f.env.servers());
+    assert!(!*f.exists.lock().unwrap());
+}
+
+#[test]
+fn hetzner_gc_lists_untracked_servers() {
+    let f = Fixture::new("hetzner-gc");
+    *f.exists.lock().unwrap() = true;
+
+    // One account failing doesn't fail `gc`
+    for (reply, msg) in FAILURES {
+        let (code, out) = f.cargo_remote(&["gc"], reply);
+        assert_eq!(code, Some(0), "{:?}: {}", reply, out);
+        assert!(
+            out.contains("could not list servers"),
+            "{:?}: {}",
+            reply,
+            out
+        );
+        assert!(out.contains(msg), "{:?}: {}", reply, out);
+    }
+
+    let (code, out) = f.cargo_remote(&["gc"], Reply::Ok);
+    assert_eq!(code, Some(0), "{}", out);
+    assert!(
+        out.contains("orphaned server 203.0.113.7 (id=42)"),
+        "{}",
+        out
+    );
+    // Not adopted without asking
+    assert!(!f.tracked(), "{}", f.env.servers());
+    let list = f
+        .api
+        .requests()
+        .into_iter()
+        .rfind(|r| r.method == "GET" && r.path.starts_with("/servers?"))
+        .expect("no list request");
+    assert!(
+        list.path.contains("label_selector=tool%3Dcargo-remote"),
+        "{}",
+        list.path
+    );
+
+    f.track();
+    let (code, out) = f.cargo_remote(&["gc"], Reply::Ok);
+    assert_eq!(code, Some(0), "{}", out);
+    assert!(out.contains("No orphaned servers"), "{}", out);
+}
+
+#[test]
+fn hetzner_adopt_records_the_ready_marker() {
+    let f = Fixture::new("hetzner-adopt");
+    *f.exists.lock().unwrap() = true;
+
+    let (code, out) = f.cargo_remote(&["adopt", "--config", "hz", "42"], Reply::Ok);
+    assert_eq!(code, Some(0), "{}", out);
+    assert!(out.contains("Adopted 203.0.113.7 (id=42)"), "{}", out);
+    // Created by hand, builds must not wait for the ready marker
+    let servers = f.env.servers();
+    assert!(servers.contains("ready_marker = false"), "{}", servers);
+
+    let (code, out) = f.cargo_remote(&["adopt", "--config", "hz", "42"], Reply::Ok);
+    assert_eq!(code, Some(3), "{}", out);
+    assert!(out.contains("already tracked"), "{}", out);
+}
+
+#[test]
+fn hetzner_retries_rate_limits_and_follows_pages() {
+    let env = hetzner_env("hetzner-retry");
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let api = MockServer::start_with_headers(move |req: &Request| {
+        let mut seen = log.lock().unwrap();
+        seen.push(Instant::now());
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let unavailable = r#"{"error":{"code":"unavailable","message":"service unavailable"}}"#;
+        match (req.method.as_str(), seen.len()) {
+            // Creating may have worked despite the 503, it is never repeated
+            ("POST", _) => (503, Vec::new(), unavailable.into()),
+            (_, 1) => (
+                429,
+                vec![("Retry-After".into(), "2".into())],
+                r#"{"error":{"code":"rate_limit_exceeded","message":"limit reached"}}"#.into(),
+            ),
+            (_, 2) => (
+                503,
+                vec![("RateLimit-Reset".into(), (now + 4).to_string())],
+                unavailable.into(),
+            ),
+            _ => {
+                let (server, next) = if req.path.contains("page=2") {
+                    (SERVER.replace("42", "43").replace(".7", ".8"), "null")
+                } else {
+                    (SERVER.to_string(), "2")
+                };
+                (
+                    200,
+                    Vec::new(),
+                    format!(
+                        r#"{{"servers":[{}],"meta":{{"pagination":{{"next_page":{}}}}}}}"#,
+                        server, next
+                    ),
+                )
+            }
+        }
+    });
+    let api_env = [("CARGO_REMOTE_HETZNER_API_URL", api.url.as_str())];
+
+    let out = env.cargo_remote(&["gc"], &api_env);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("orphaned server 203.0.113.7 (id=42)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("orphaned server 203.0.113.8 (id=43)"),
+        "{}",
+        stdout
+    );
+
+    let pages: Vec<String> = api.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(pages.len(), 4, "{:?}", pages);
+    assert!(
+        pages[2].contains("page=1") && pages[3].contains("page=2"),
+        "{:?}",
+        pages
+    );
+    // Waited as told, not the 1s / 2s of the exponential backoff
+    let seen = seen.lock().unwrap().clone();
+    assert!(seen[1] - seen[0] >= Duration::from_secs(2), "{:?}", seen);
+    assert!(seen[2] - seen[1] >= Duration::from_secs(3), "{:?}", seen);
+
+    let out = env.cargo_remote(&["begin", "--config", "hz"], &api_env);
+    assert_eq!(out.status.code(), Some(3));
+    let creates = api
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "POST")
+        .count();
+    assert_eq!(creates, 1);
+    assert!(env.servers().is_empty(), "{}", env.servers());
+}
+
+#[test]
+fn hetzner_gc_names_the_renting_config() {
+    let env = hetzner_env("hetzner-gc-labels");
+    let api = MockServer::start(|_: &Request| {
+        let other = SERVER
+            .replace("42", "43")
+            .replace(".7", ".8")
+            .replace(r#""config":"hz""#, r#""config":"other""#);
+        // Still starting, no IPv4 yet
+        let starting = SERVER
+            .replace("42", "44")
+            .replace(r#"{"ipv4":{"ip":"203.0.113.7"}}"#, r#"{"ipv4":null}"#);
+        (
+            200,
+            format!(
+                r#"{{"servers":[{},{},{}],"meta":{{"pagination":{{"next_page":null}}}}}}"#,
+                SERVER, other, starting
+            ),
+        )
+    });
+
+    let out = env.cargo_remote(
+        &["gc"],
+        &[("CARGO_REMOTE_HETZNER_API_URL", api.url.as_str())],
+    );
+    let output = format!(
+        "{}{}",
+        String::from_utf8_lossy(&out.stdout),
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(out.status.success(), "{}", output);
+    assert!(
+        output.contains("-hz] orphaned server 203.0.113.7 (id=42)"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("-other] orphaned server 203.0.113.8 (id=43)"),
+        "{}",
+        output
+    );
+    assert!(output.contains("skipping server 44"), "{}", output);
+}
+
+#[test]
+fn hetzner_status_skips_the_marker_of_adopted_servers() {
+    let env = hetzner_env("hetzner-status-adopted");
+    let api = MockServer::start(|_: &Request| {
+        (
+            200,
+            format!(
+                r#"{{"server":{}}}"#,
+                SERVER.replace(r#""status":"off""#, r#""status":"running""#)
+            ),
+        )
+    });
+    std::fs::write(
+        env.xdg.join("cargo-remote/servers.toml"),
+        "[[projects]]\nprovider = \"Hetzner\"\nconfig = \"hz\"\nid = \"42\"\nhost = \"203.0.113.7\"\nport = 22\nusername = \"root\"\nready_marker = false\n",
+    )
+    .unwrap();
+
+    let out = env.cargo_remote(
+        &["status"],
+        &[("CARGO_REMOTE_HETZNER_API_URL", api.url.as_str())],
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}", stdout);
+    assert!(stdout.contains("203.0.113.7 ready: n/a"), "{}", stdout);
+    assert!(!stdout.contains("cloud-init"), "{}", stdout);
+}
+
+#[test]
+fn hetzner_ephemeral_servers_are_not_selected() {
+    let env = hetzner_env("hetzner-ephemeral");
+    // Powered off for the snapshot by `image bake`
+    let api = MockServer::start(|_: &Request| (200, format!(r#"{{"server":{}}}"#, SERVER)));
+    std::fs::write(
+        env.xdg.join("cargo-remote/servers.toml"),
+        "[[projects]]\nprovider = \"Hetzner\"\nconfig = \"hz\"\nid = \"42\"\nhost = \"203.0.113.7\"\nport = 22\nusername = \"root\"\nstate = \"ephemeral\"\n",
+    )
+    .unwrap();
+    let api_env = [("CARGO_REMOTE_HETZNER_API_URL", api.url.as_str())];
+
+    let out = env.cargo_remote(&["build", "--config", "hz"], &api_env);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!out.status.success(), "{}", stdout);
+    assert!(stdout.contains("no active cloud server"), "{}", stdout);
+
+    let out = env.cargo_remote(&["status"], &api_env);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{}", stdout);
+    assert!(stdout.contains("powered off, ephemeral"), "{}", stdout);
+    assert!(env.servers().contains("state = \"ephemeral\""));
+}
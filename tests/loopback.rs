@@ -0,0 +1,83 @@
+mod common;
+
+use common::{assert_success, has_rsync, TestEnv};
+
+/// Exits with the code given as first argument
+const MAIN: &str = "fn main() {\n    let code = std::env::args().nth(1).map_or(0, |c| c.parse().unwrap());\n    println!(\"running {}\", env!(\"CARGO_PKG_NAME\"));\n    std::process::exit(code);\n}\n";
+
+fn fixture(name: &str) -> Option<(TestEnv, String)> {
+    if !has_rsync() {
+        eprintln!("rsync is not installed, skipping");
+        return None;
+    }
+    let env = TestEnv::new(name);
+    std::fs::write(env.project.join("src/main.rs"), MAIN).unwrap();
+    std::fs::write(env.project.join("notes.txt"), "synced").unwrap();
+    std::fs::write(env.project.join(".env"), "SECRET=1").unwrap();
+    let remote = env.dir.join("remote").to_string_lossy().into_owned();
+    Some((env, remote))
+}
+
+#[test]
+fn loopback_build_copies_back_artifacts() {
+    let Some((env, remote)) = fixture("loopback-build") else {
+        return;
+    };
+
+    let out = env.cargo_remote(
+        &["build", "--loopback", &remote, "--copy-back", "debug"],
+        &[],
+    );
+    assert_success(&out);
+
+    // Synced without hidden files, built there and copied back
+    let builds: Vec<_> = std::fs::read_dir(env.dir.join("remote/remote-builds"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(builds.len(), 1, "{:?}", builds);
+    assert!(builds[0].join("notes.txt").is_file());
+    assert!(!builds[0].join(".env").exists());
+    assert!(builds[0].join("target/debug/p").is_file());
+    assert!(env.project.join("target/debug/p").is_file());
+    assert!(env.project.join("Cargo.lock").is_file());
+
+    // A failing build is reported with cargo's exit code
+    std::fs::write(env.project.join("src/main.rs"), "fn main() {").unwrap();
+    let out = env.cargo_remote(&["build", "--loopback", &remote], &[]);
+    assert_eq!(out.status.code(), Some(101));
+}
+
+#[test]
+fn loopback_run_propagates_exit_code() {
+    let Some((env, remote)) = fixture("loopback-run") else {
+        return;
+    };
+
+    let out = env.cargo_remote(&["run", "--loopback", &remote, "--", "--", "3"], &[]);
+    assert_eq!(
+        out.status.code(),
+        Some(3),
+        "stderr:\n{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("running p"));
+
+    // The toolchain is selected per command, `rustup default` of this machine is left alone
+    let out = env.cargo_remote(
+        &[
+            "run",
+            "--loopback",
+            &remote,
+            "--rustup-default",
+            "no-such-toolchain",
+        ],
+        &[],
+    );
+    assert!(!out.status.success());
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("no-such-toolchain"),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}